@@ -0,0 +1,132 @@
+//! The bot's end of the websocket, which speaks the same protocol the browser Client does.
+use comn::{rmps, ClientMessage, Snapshot, PROTOCOL_VERSION};
+// networking
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use tungstenite::{handshake::HandshakeError, Message, WebSocket};
+use url::Url;
+// util
+use log::*;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The address of the Server couldn't be understood.
+    Url(url::ParseError),
+    /// Couldn't reach the Server at all.
+    Io(std::io::Error),
+    /// Something went wrong talking to the Server over the websocket.
+    WebSocket(tungstenite::Error),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Url(e) => write!(f, "bad server address: {}", e),
+            Error::Io(e) => write!(f, "couldn't reach server: {}", e),
+            Error::WebSocket(e) => write!(f, "websocket error: {}", e),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+/// Doesn't block after it's been established;
+/// `recv` just returns whatever's shown up since it was last called.
+pub struct Connection {
+    ws: WebSocket<TcpStream>,
+    closed: bool,
+}
+impl Connection {
+    /// Connects to the Server at the given websocket address,
+    /// i.e. "ws://127.0.0.1:3012", and says Hello.
+    pub fn connect(server: &str) -> Result<Self, Error> {
+        let url = Url::parse(server).map_err(Error::Url)?;
+        let addr = url
+            .socket_addrs(|| Some(80))
+            .map_err(Error::Io)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Io(ErrorKind::AddrNotAvailable.into()))?;
+
+        let stream = TcpStream::connect(addr).map_err(Error::Io)?;
+        let (ws, _) = tungstenite::client(url, stream).map_err(|e| match e {
+            HandshakeError::Failure(e) => Error::WebSocket(e),
+            // the stream is still blocking, so the handshake can't be interrupted
+            HandshakeError::Interrupted(_) => unreachable!(),
+        })?;
+        ws.get_ref().set_nonblocking(true).map_err(Error::Io)?;
+
+        let mut connection = Self { ws, closed: false };
+        // the Server won't listen to anything else until we've said this.
+        connection.send(ClientMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_build: concat!("bot ", env!("CARGO_PKG_VERSION")).to_string(),
+            // bots that lose their connection just stop.
+            resume_token: None,
+        });
+
+        Ok(connection)
+    }
+
+    /// Whether the Server has hung up on us, or we've lost it.
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn send(&mut self, msg: ClientMessage) {
+        if self.closed {
+            return;
+        }
+
+        let bytes = rmps::encode::to_vec(&msg).expect("Couldn't encode ClientMessage!");
+        match self.ws.write_message(Message::Binary(bytes)) {
+            Ok(()) => {}
+            // it's been queued up, and'll go out the next time we flush.
+            Err(tungstenite::Error::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => self.lost(e),
+        }
+    }
+
+    /// Every Snapshot the Server has sent since this was last called.
+    pub fn recv(&mut self) -> Vec<Snapshot> {
+        let mut snapshots = Vec::new();
+
+        while !self.closed {
+            match self.ws.read_message() {
+                Ok(Message::Binary(bytes)) => match rmps::from_read_ref(&bytes) {
+                    Ok(snapshot) => snapshots.push(snapshot),
+                    Err(e) => error!("couldn't decode Snapshot: {}", e),
+                },
+                Ok(Message::Close(frame)) => {
+                    info!("Server hung up: {:?}", frame);
+                    self.closed = true;
+                }
+                // tungstenite handles pings on its own
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => self.lost(e),
+            }
+        }
+
+        // reading gives tungstenite a chance to send whatever it's got queued up,
+        // but if there was nothing to read it might still have some left.
+        if !self.closed {
+            match self.ws.write_pending() {
+                Ok(()) => {}
+                Err(tungstenite::Error::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => self.lost(e),
+            }
+        }
+
+        snapshots
+    }
+
+    fn lost(&mut self, e: tungstenite::Error) {
+        match e {
+            tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+                info!("connection to Server closed")
+            }
+            e => error!("lost connection to Server: {}", e),
+        }
+        self.closed = true;
+    }
+}
@@ -0,0 +1,268 @@
+//! Bots connect to the Server just like the browser Client does,
+//! but can run anywhere and are told what to do by a script of Actions
+//! rather than by someone at a keyboard.
+//! They're used for end-to-end tests, and for seeing how the Server
+//! holds up under a lot of players.
+use comn::controls::{step, Heading, Input, INPUTS_PER_SECOND};
+use comn::item::{DropRequest, Inventory, PickupRequest, SlotIndex};
+use comn::prelude::*;
+use comn::specs::{prelude::*, WorldExt};
+use comn::{ClientMessage, NetId, ServerMessage};
+// util
+use log::*;
+use std::collections::VecDeque;
+
+mod connection;
+mod mirror;
+pub use connection::{Connection, Error};
+pub use mirror::Mirror;
+
+/// How close a bot has to get to somewhere it's walking before it counts as there.
+pub const ARRIVAL_DISTANCE: f32 = 0.2;
+/// How many updates a bot keeps walking without getting any closer
+/// to where it's going before it gives up, say because there's a wall in the way.
+pub const STUCK_UPDATES: u32 = INPUTS_PER_SECOND as u32 * 2;
+
+#[derive(Clone, Debug)]
+/// The things a bot can be told to do, one after another.
+pub enum Action {
+    /// Walk in a straight line until we're ARRIVAL_DISTANCE away from here,
+    /// or until we've gone STUCK_UPDATES without getting any closer.
+    WalkTo(Vec2),
+    /// Ask to pick up the item with this NetId.
+    PickUp(NetId),
+    /// Ask to pick up whichever item is closest, if any are close enough,
+    /// like a player clicking on the item next to them.
+    PickUpNearest,
+    /// Ask to drop whatever's in this slot of our inventory.
+    Drop(SlotIndex),
+    /// Stand still for this many updates.
+    Wait(u32),
+}
+
+pub struct Bot {
+    connection: Connection,
+    pub mirror: Mirror,
+    /// What we've still got to do, front first.
+    script: VecDeque<Action>,
+    /// Why the Server turned us away, or wouldn't let us log in, if it did.
+    pub rejected: Option<String>,
+    /// The account we log into once we're welcomed, and its password.
+    username: String,
+    password: String,
+    /// The Inputs we've sent that the Server hasn't told us it's applied yet.
+    unacked: VecDeque<Input>,
+    next_seq: u32,
+    /// While we're walking somewhere, the closest we've gotten to it,
+    /// and how many updates it's been since we got any closer.
+    progress: Option<(f32, u32)>,
+}
+impl Bot {
+    /// Connects to the Server at the given websocket address, i.e. "ws://127.0.0.1:3012".
+    /// The bot logs into this account as soon as it's welcomed in,
+    /// (making it if it has to) and asks to be spawned once it has.
+    pub fn connect(server: &str, username: &str, password: &str) -> Result<Self, Error> {
+        Ok(Self {
+            connection: Connection::connect(server)?,
+            mirror: Mirror::default(),
+            script: VecDeque::new(),
+            rejected: None,
+            username: username.to_string(),
+            password: password.to_string(),
+            unacked: VecDeque::new(),
+            next_seq: 0,
+            progress: None,
+        })
+    }
+
+    /// Whether we can still talk to the Server.
+    pub fn connected(&self) -> bool {
+        !self.connection.closed()
+    }
+
+    /// Whether we've been spawned in and can still talk to the Server.
+    pub fn playing(&self) -> bool {
+        self.mirror.player.is_some() && self.connected()
+    }
+
+    /// Whether we've done everything we were told to.
+    pub fn idle(&self) -> bool {
+        self.script.is_empty()
+    }
+
+    /// Adds this to the end of the script.
+    pub fn then(&mut self, action: Action) -> &mut Self {
+        self.script.push_back(action);
+        self
+    }
+
+    /// Walks to each of these points, in order.
+    pub fn walk_path<I: IntoIterator<Item = Vec2>>(&mut self, path: I) -> &mut Self {
+        self.script.extend(path.into_iter().map(Action::WalkTo));
+        self
+    }
+
+    /// Where our player is, counting the Inputs the Server hasn't applied yet.
+    pub fn position(&self) -> Option<Iso2> {
+        let mut iso = self.mirror.pos(self.mirror.player?)?;
+        for Input { heading, .. } in &self.unacked {
+            step(&mut iso, &heading.dir, 1.0 / INPUTS_PER_SECOND);
+        }
+        Some(iso)
+    }
+
+    /// What the Server last told us was in our inventory.
+    pub fn inventory(&self) -> Option<Inventory> {
+        self.mirror
+            .world
+            .read_storage::<Inventory>()
+            .get(self.mirror.player?)
+            .cloned()
+    }
+
+    /// The NetId of the item closest to us, so long as it's close enough to pick up.
+    pub fn nearest_item(&self) -> Option<NetId> {
+        use comn::item::MAX_INTERACTION_DISTANCE_SQUARED;
+        let here = self.position()?.translation.vector;
+        let world = &self.mirror.world;
+
+        (
+            &world.entities(),
+            &world.read_storage::<Item>(),
+            &world.read_storage::<Pos>(),
+        )
+            .join()
+            .map(|(ent, _, Pos(iso))| (ent, (iso.translation.vector - here).magnitude_squared()))
+            .filter(|(_, dist)| *dist < MAX_INTERACTION_DISTANCE_SQUARED)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .and_then(|(ent, _)| self.mirror.net_id(ent))
+    }
+
+    /// This should be called INPUTS_PER_SECOND times a second;
+    /// bots walk one Input's worth each time it's called.
+    pub fn update(&mut self) {
+        for snapshot in self.connection.recv() {
+            for msg in &snapshot.messages {
+                match msg {
+                    ServerMessage::Welcome { your_net_id, .. } => {
+                        debug!("bot welcomed as {:?}", your_net_id);
+                        self.connection.send(ClientMessage::Login {
+                            username: self.username.clone(),
+                            password: self.password.clone(),
+                        });
+                    }
+                    ServerMessage::LoggedIn(username) => {
+                        debug!("bot logged in as {}", username);
+                        self.connection.send(ClientMessage::SpawnPlayer);
+                    }
+                    ServerMessage::Ping(id) => self.connection.send(ClientMessage::Pong(*id)),
+                    ServerMessage::LoginFailed(reason) => {
+                        warn!("bot couldn't log in as {}: {}", self.username, reason);
+                        self.rejected = Some(reason.clone());
+                    }
+                    ServerMessage::Rejected(reason) => {
+                        warn!("bot turned away: {}", reason);
+                        self.rejected = Some(reason.clone());
+                    }
+                    _ => {}
+                }
+            }
+
+            for update in self.mirror.apply(snapshot) {
+                if let Some(ack) = update.ack {
+                    while self.unacked.front().map_or(false, |i| i.seq <= ack) {
+                        self.unacked.pop_front();
+                    }
+                }
+            }
+        }
+
+        if self.playing() {
+            self.act();
+        }
+    }
+
+    /// Does (or keeps doing) whatever's at the front of the script.
+    fn act(&mut self) {
+        let action = match self.script.front() {
+            Some(action) => action.clone(),
+            None => return,
+        };
+
+        let done = match action {
+            Action::WalkTo(goal) => match self.position() {
+                Some(iso) => {
+                    let to_goal = goal - iso.translation.vector;
+                    let distance = to_goal.magnitude();
+                    // it only counts as getting closer if it's by a bit,
+                    // so that inching along a wall doesn't keep us at it forever.
+                    let stalled = match &mut self.progress {
+                        Some((closest, stalled)) if distance > *closest - ARRIVAL_DISTANCE => {
+                            *stalled += 1;
+                            *stalled
+                        }
+                        progress => {
+                            *progress = Some((distance, 0));
+                            0
+                        }
+                    };
+
+                    if distance < ARRIVAL_DISTANCE {
+                        true
+                    } else if stalled >= STUCK_UPDATES {
+                        warn!(
+                            "bot {} got stuck {} away from {:?}, giving up",
+                            self.username, distance, goal
+                        );
+                        true
+                    } else {
+                        self.input(na::Unit::new_normalize(to_goal));
+                        false
+                    }
+                }
+                None => false,
+            },
+            Action::PickUp(id) => {
+                self.connection
+                    .send(ClientMessage::PickupRequest(PickupRequest { id }));
+                true
+            }
+            Action::PickUpNearest => {
+                match self.nearest_item() {
+                    Some(id) => self
+                        .connection
+                        .send(ClientMessage::PickupRequest(PickupRequest { id })),
+                    None => debug!("bot found nothing close enough to pick up"),
+                }
+                true
+            }
+            Action::Drop(item_index) => {
+                self.connection
+                    .send(ClientMessage::DropRequest(DropRequest { item_index }));
+                true
+            }
+            Action::Wait(0) => true,
+            Action::Wait(left) => {
+                self.script[0] = Action::Wait(left - 1);
+                false
+            }
+        };
+
+        if done {
+            self.script.pop_front();
+            self.progress = None;
+        }
+    }
+
+    /// Sends along an Input going this way, remembering it until it's acknowledged.
+    fn input(&mut self, dir: na::Unit<Vec2>) {
+        let input = Input {
+            seq: self.next_seq,
+            heading: Heading { dir },
+        };
+        self.next_seq += 1;
+
+        self.connection.send(ClientMessage::Input(input.clone()));
+        self.unacked.push_back(input);
+    }
+}
@@ -0,0 +1,79 @@
+//! Connects a swarm of bots to a Server, and has them wander around
+//! picking up and dropping whatever they come across, forever.
+use bot::{Action, Bot};
+use comn::controls::INPUTS_PER_SECOND;
+use comn::item::SlotIndex;
+use comn::prelude::*;
+use log::*;
+use rand::Rng;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "bot", about = "Load tests a storked Server with bots.")]
+struct Opts {
+    /// Websocket address of the Server
+    #[structopt(short, long, default_value = "ws://127.0.0.1:3012")]
+    server: String,
+
+    /// How many bots to connect
+    #[structopt(short, long, default_value = "100")]
+    count: usize,
+
+    /// How far the bots wander from wherever they are each time they set off
+    #[structopt(long, default_value = "20.0")]
+    range: f32,
+
+    /// The password every bot logs in with; each one has its own account, bot0, bot1...
+    #[structopt(long, default_value = "beepboop")]
+    password: String,
+}
+
+fn main() {
+    pretty_env_logger::init();
+    let opts = Opts::from_args();
+
+    let mut bots = (0..opts.count)
+        .filter_map(
+            |i| match Bot::connect(&opts.server, &format!("bot{}", i), &opts.password) {
+                Ok(bot) => Some(bot),
+                Err(e) => {
+                    error!("bot {} couldn't connect: {}", i, e);
+                    None
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    info!("{} bots connected!", bots.len());
+
+    let mut rng = rand::thread_rng();
+    let mut fixedstep = fixedstep::FixedStep::start(INPUTS_PER_SECOND as f64);
+
+    while !bots.is_empty() {
+        while fixedstep.update() {
+            for bot in bots.iter_mut() {
+                bot.update();
+
+                // whenever a bot runs out of things to do, it finds somewhere new to go,
+                // near wherever it is now, since that's somewhere it knows it can be.
+                let here = bot.position().filter(|_| bot.playing() && bot.idle());
+                if let Some(here) = here {
+                    let goal = here.translation.vector
+                        + Vec2::new(
+                            rng.gen_range(-opts.range, opts.range),
+                            rng.gen_range(-opts.range, opts.range),
+                        );
+                    bot.then(Action::WalkTo(goal))
+                        .then(Action::PickUpNearest)
+                        .then(Action::Wait(rng.gen_range(0, INPUTS_PER_SECOND as u32)))
+                        .then(Action::Drop(SlotIndex::Loose(0, 0)));
+                }
+            }
+
+            let before = bots.len();
+            bots.retain(|bot| bot.connected());
+            if bots.len() < before {
+                warn!("{} bots lost their connection", before - bots.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! A copy of the parts of the Server's world the bot has been told about.
+//! Unlike the browser Client's, nothing here is interpolated;
+//! entities are simply put wherever the Server last said they were.
+use comn::prelude::*;
+use comn::specs::{prelude::*, WorldExt};
+use comn::{net::UpdatePosition, NetComponent, NetId, ServerMessage, Snapshot, Tick};
+// util
+use bimap::BiMap;
+use log::*;
+
+pub struct Mirror {
+    pub world: World,
+    ids: BiMap<NetId, Entity>,
+    /// Our own player entity, once it's been spawned.
+    pub player: Option<Entity>,
+    /// The last tick the Server sent us a Snapshot for.
+    pub tick: Tick,
+}
+impl Default for Mirror {
+    fn default() -> Self {
+        let mut world = World::new();
+        comn::net::register_net_components(&mut world);
+        world.insert(comn::art::TileMap::default());
+
+        Self {
+            world,
+            ids: BiMap::new(),
+            player: None,
+            tick: Tick::default(),
+        }
+    }
+}
+impl Mirror {
+    /// The local entity the Server refers to with this NetId, if we know of it.
+    pub fn ent(&self, id: NetId) -> Option<Entity> {
+        self.ids.get_by_left(&id).cloned()
+    }
+
+    /// The NetId the Server refers to this local entity with.
+    pub fn net_id(&self, ent: Entity) -> Option<NetId> {
+        self.ids.get_by_right(&ent).cloned()
+    }
+
+    /// Where the Server last said this entity was.
+    pub fn pos(&self, ent: Entity) -> Option<Iso2> {
+        self.world
+            .read_storage::<Pos>()
+            .get(ent)
+            .map(|Pos(iso)| *iso)
+    }
+
+    /// Applies everything in a Snapshot to the world, all at once.
+    /// UpdatePositions for our own player are handed back,
+    /// since those are what predictions are checked against.
+    pub fn apply(&mut self, Snapshot { tick, messages }: Snapshot) -> Vec<UpdatePosition> {
+        let mut own_updates = Vec::new();
+
+        for msg in messages {
+            use ServerMessage::*;
+
+            match msg {
+                // the Bot handles these itself
+                Welcome { .. } | Rejected(_) | LoggedIn(_) | LoginFailed(_) => {}
+                Ping(_) | Pong(_) => {}
+                // bots have nothing to say
+                Chat { .. } => {}
+                TileMap(tiles) => self.world.insert(tiles),
+                SetTile(cell, tile) => {
+                    let mut tiles = self.world.write_resource::<comn::art::TileMap>();
+                    if tiles.contains(cell) {
+                        tiles.set(cell, tile)
+                    } else {
+                        error!("Can't set tile off of the TileMap at {:?}", cell);
+                    }
+                }
+                NewEnt(id) => {
+                    let ent = self.world.create_entity().build();
+                    self.ids.insert(id, ent);
+                }
+                InsertComp(id, net_comp) => match self.ent(id) {
+                    Some(ent) => match net_comp {
+                        NetComponent::LocalPlayer(_) => self.player = Some(ent),
+                        NetComponent::UpdatePosition(update) => {
+                            if self.player == Some(ent) {
+                                own_updates.push(update.clone());
+                            }
+                            self.world
+                                .write_storage()
+                                .insert(ent, Pos(update.iso))
+                                .expect("Couldn't insert Pos from UpdatePosition");
+                        }
+                        _ => net_comp.insert(ent, &self.world.read_resource()),
+                    },
+                    None => error!("Can't insert component for unknown entity {:?}", id),
+                },
+                RemoveEnt(id) => {
+                    if let Some((_, ent)) = self.ids.remove_by_left(&id) {
+                        if self.player == Some(ent) {
+                            self.player = None;
+                        }
+                        self.world
+                            .delete_entity(ent)
+                            .expect("Couldn't delete removed entity");
+                    }
+                }
+                RemoveComp(id, kind) => match self.ent(id) {
+                    Some(ent) => kind.remove(ent, &self.world.read_resource()),
+                    None => error!("Can't remove component for unknown entity {:?}", id),
+                },
+            }
+
+            // components inserted lazily have to be in place before the next message,
+            // in case that one refers to them.
+            self.world.maintain();
+        }
+
+        self.tick = tick;
+        own_updates
+    }
+}
+
+#[test]
+fn mirror_applies_snapshot() {
+    use comn::item::Inventory;
+
+    let mut mirror = Mirror::default();
+    let (me, key) = (NetId(0), NetId(1));
+    let own_update = UpdatePosition {
+        iso: Iso2::translation(2.0, 3.0),
+        ack: Some(4),
+    };
+
+    let own_updates = mirror.apply(Snapshot {
+        tick: Tick(7),
+        messages: vec![
+            ServerMessage::NewEnt(me),
+            ServerMessage::InsertComp(me, comn::net::LocalPlayer.into()),
+            ServerMessage::InsertComp(me, Inventory::character().into()),
+            ServerMessage::InsertComp(me, own_update.into()),
+            ServerMessage::NewEnt(key),
+            ServerMessage::InsertComp(key, Item::Misc.into()),
+            ServerMessage::InsertComp(key, Pos(Iso2::translation(5.0, 5.0)).into()),
+            ServerMessage::RemoveEnt(key),
+        ],
+    });
+
+    let player = mirror.player.expect("no player after LocalPlayer");
+    assert_eq!(mirror.tick, Tick(7));
+    assert_eq!(own_updates.len(), 1);
+    assert_eq!(own_updates[0].ack, Some(4));
+    assert_eq!(
+        mirror.pos(player).map(|iso| iso.translation.vector),
+        Some(Vec2::new(2.0, 3.0))
+    );
+    assert!(mirror
+        .world
+        .read_storage::<Inventory>()
+        .get(player)
+        .is_some());
+    assert_eq!(mirror.ent(key), None);
+}
@@ -14,7 +14,7 @@ use prelude::*;
 
 mod renderer {
     use crate::prelude::*;
-    use comn::art::{Animate, AnimationData, Appearance, SpritesheetData, Tile};
+    use comn::art::{Animate, AnimationData, Appearance, SpritesheetData, TileMap, TILE_SIZE};
     use comn::enum_iterator::IntoEnumIterator;
     use std::collections::HashMap;
     use stdweb::{
@@ -71,13 +71,13 @@ mod renderer {
 
     impl<'a> System<'a> for Render {
         type SystemData = (
+            Read<'a, TileMap>,
             ReadStorage<'a, Appearance>,
             ReadStorage<'a, Pos>,
-            ReadStorage<'a, Tile>,
             WriteStorage<'a, Animate>,
         );
 
-        fn run(&mut self, (appearances, poses, tiles, mut animates): Self::SystemData) {
+        fn run(&mut self, (tiles, appearances, poses, mut animates): Self::SystemData) {
             self.ctx.set_fill_style_color("black");
 
             // black background
@@ -89,17 +89,17 @@ mod renderer {
                 win.inner_height().into(),
             );
 
-            // tiles are rendered as if their origin was their center on the X and Y.
+            // tiles are rendered as if their origin was the center of their cell.
             // also, tiles are rendered first so that everything else can step on them.
-            for (appearance, &Pos(iso), _) in (&appearances, &poses, &tiles).join() {
-                const SIZE: f32 = 2.0;
+            for (cell, appearance) in tiles.tiles() {
+                let loc = TileMap::loc(cell);
                 self.ctx
                     .draw_image_d(
                         self.imgs[appearance].clone(),
-                        ((iso.translation.vector.x - SIZE / 2.0) * ZOOM) as f64,
-                        ((iso.translation.vector.y - SIZE / 2.0) * ZOOM) as f64,
-                        (SIZE * ZOOM) as f64,
-                        (SIZE * ZOOM) as f64,
+                        ((loc.x - TILE_SIZE / 2.0) * ZOOM) as f64,
+                        ((loc.y - TILE_SIZE / 2.0) * ZOOM) as f64,
+                        (TILE_SIZE * ZOOM) as f64,
+                        (TILE_SIZE * ZOOM) as f64,
                     )
                     .expect("Couldn't draw tile!");
             }
@@ -107,8 +107,8 @@ mod renderer {
             // other entities are rendered as if their origin was
             // their center on the X,
             // but their bottom on the Y.
-            for (appearance, &Pos(iso), animaybe, _) in
-                (&appearances, &poses, (&mut animates).maybe(), !&tiles).join()
+            for (appearance, &Pos(iso), animaybe) in
+                (&appearances, &poses, (&mut animates).maybe()).join()
             {
                 const SIZE: f32 = 2.0;
                 if let Some(anim) = animaybe {
@@ -157,15 +157,22 @@ mod renderer {
 }
 
 mod net {
+    use crate::controls::Prediction;
     use crate::prelude::*;
     use bimap::BiMap;
-    use comn::{NetComponent, NetMessage, Pos};
+    use comn::art::TileMap;
+    use comn::net::{ResumeToken, UpdatePosition};
+    use comn::ping::Latency;
+    use comn::{
+        ClientMessage, NetComponent, NetId, Pos, ServerMessage, Snapshot, Tick, PROTOCOL_VERSION,
+    };
+    use std::collections::VecDeque;
     use std::sync::{Arc, Mutex};
     use stdweb::{
         unstable::TryInto,
         web::{
             event::{SocketCloseEvent, SocketErrorEvent, SocketMessageEvent, SocketOpenEvent},
-            ArrayBuffer, IEventTarget, WebSocket,
+            set_timeout, ArrayBuffer, IEventTarget, SocketReadyState, WebSocket,
         },
         Value,
     };
@@ -173,63 +180,111 @@ mod net {
     #[derive(Default)]
     pub struct Player(pub Option<Entity>);
 
+    /// How long to wait before trying to reach the Server again, in milliseconds.
+    const RECONNECT_DELAY: u32 = 1000;
+
     pub struct ServerConnection {
-        ws: WebSocket,
-        pub message_queue: Arc<Mutex<Vec<NetMessage>>>,
+        /// This is replaced by a new one whenever we lose the Server; see `connect`.
+        ws: Arc<Mutex<Option<WebSocket>>>,
+        pub message_queue: Arc<Mutex<Vec<Snapshot>>>,
+        /// From the Server's last Welcome; see `comn::net::ResumeToken`.
+        pub resume_token: Arc<Mutex<Option<ResumeToken>>>,
     }
     impl ServerConnection {
         #[inline]
-        fn send(&self, msg: NetMessage) {
-            self.ws
-                .send_bytes(&rmps::encode::to_vec(&msg).expect("Couldn't encode NetMessage!"))
-                .expect("Couldn't send NetMessage to server!");
-        }
-
-        /* I'm not sure why/when/how you'd ever even actually use this on the client.
-         * The server should definitely be in control of when new things are made,
-         * even if indirectly the Client ends up requesting that to happen.
-         * For that reason, this is prevented from working on the serverside.
-         * Instead, it's used internally to register a new player; if you send
-         * this request through hacking or some other means, you'll just get
-         * your player reset :grin:
-        #[inline]
-        pub fn new_ent(&self, ent: specs::Entity) {
-            self.send(NetMessage::NewEnt(ent.id()));
-        }*/
+        /// The Client can only tell the Server what it would like to happen;
+        /// see `comn::ClientMessage`.
+        pub fn send(&self, msg: ClientMessage) {
+            match &*self.ws.lock().expect("The websocket is locked!") {
+                Some(ws) => Self::send_over(ws, msg),
+                None => trace!("dropping {:?}, we haven't reached the Server yet", msg),
+            }
+        }
 
-        #[inline]
-        pub fn insert_comp<C: Into<NetComponent>>(
-            &self,
-            // The client can only request that components are
-            // inserted onto itself.
-            // ent: specs::Entity,
-            comp: C,
-        ) {
-            // just using a 0 here for the entity ID since they can
-            // only insert components onto their own entity.
-            self.send(NetMessage::InsertComp(0, comp.into()));
+        /// Asks whoever's playing which account to log into, then tries to.
+        /// If they'd rather not say, we just never log in.
+        pub fn log_in(&self) {
+            let username = js! { return prompt("Username"); }.into_string();
+            let password = js! { return prompt("Password"); }.into_string();
+            match (username, password) {
+                (Some(username), Some(password)) => {
+                    self.send(ClientMessage::Login { username, password })
+                }
+                _ => info!("Not logging in"),
+            }
         }
-    }
 
-    impl Default for ServerConnection {
-        fn default() -> Self {
-            let ws = WebSocket::new("ws://127.0.0.1:3012")
+        fn send_over(ws: &WebSocket, msg: ClientMessage) {
+            // while we're reconnecting, there's nobody to hear this.
+            if ws.ready_state() != SocketReadyState::Open {
+                trace!("dropping {:?}, we aren't connected", msg);
+                return;
+            }
+            ws.send_bytes(&rmps::encode::to_vec(&msg).expect("Couldn't encode ClientMessage!"))
+                .expect("Couldn't send ClientMessage to server!");
+        }
+
+        /// Opens a websocket to the Server and puts it in `ws`.
+        /// If that connection is lost, a new one is opened in its place,
+        /// which picks up where the old one left off if it can.
+        fn connect(
+            ws: Arc<Mutex<Option<WebSocket>>>,
+            message_queue: Arc<Mutex<Vec<Snapshot>>>,
+            resume_token: Arc<Mutex<Option<ResumeToken>>>,
+        ) {
+            let socket = WebSocket::new("ws://127.0.0.1:3012")
                 .unwrap_or_else(|e| panic!("couldn't reach server: {}", e));
-            let message_queue = Arc::new(Mutex::new(Vec::new()));
 
-            ws.add_event_listener(|_: SocketOpenEvent| {
-                info!("Connected to server!");
+            socket.add_event_listener({
+                let socket = socket.clone();
+                let resume_token = resume_token.clone();
+
+                move |_: SocketOpenEvent| {
+                    info!("Connected to server!");
+
+                    // the Server won't listen to anything else until we've said this.
+                    Self::send_over(
+                        &socket,
+                        ClientMessage::Hello {
+                            protocol_version: PROTOCOL_VERSION,
+                            client_build: env!("CARGO_PKG_VERSION").to_string(),
+                            resume_token: *resume_token
+                                .lock()
+                                .expect("The resume token is locked!"),
+                        },
+                    );
+                }
             });
 
-            ws.add_event_listener(|e: SocketErrorEvent| {
+            socket.add_event_listener(|e: SocketErrorEvent| {
                 error!("Errror connecting to {:?}s", e);
             });
 
-            ws.add_event_listener(|e: SocketCloseEvent| {
-                error!("Server Connection Closed: {}s", e.reason());
+            socket.add_event_listener({
+                let ws = ws.clone();
+                let message_queue = message_queue.clone();
+                let resume_token = resume_token.clone();
+
+                move |e: SocketCloseEvent| {
+                    error!("Server Connection Closed: {}s", e.reason());
+
+                    // the Server only hangs up on purpose when it's turned us away,
+                    // so there's no sense trying again then.
+                    if !e.was_clean() {
+                        let (ws, message_queue, resume_token) =
+                            (ws.clone(), message_queue.clone(), resume_token.clone());
+                        set_timeout(
+                            move || {
+                                info!("Trying to reach the Server again");
+                                Self::connect(ws, message_queue, resume_token)
+                            },
+                            RECONNECT_DELAY,
+                        );
+                    }
+                }
             });
 
-            ws.add_event_listener({
+            socket.add_event_listener({
                 let msgs = message_queue.clone();
 
                 move |msg: SocketMessageEvent| {
@@ -243,7 +298,7 @@ mod net {
                         let mut msgs = msgs.lock().expect("The Server Message Queue is locked!");
                         msgs.push(
                             rmps::from_read_ref::<Vec<u8>, _>(&buf.into())
-                                .expect("couldn't read net message bytes"),
+                                .expect("couldn't read snapshot bytes"),
                         );
                     };
 
@@ -259,58 +314,217 @@ mod net {
                 }
             });
 
-            Self { ws, message_queue }
+            *ws.lock().expect("The websocket is locked!") = Some(socket);
         }
     }
 
-    use comn::net::UpdatePosition;
+    impl Default for ServerConnection {
+        fn default() -> Self {
+            let sc = Self {
+                ws: Arc::new(Mutex::new(None)),
+                message_queue: Arc::new(Mutex::new(Vec::new())),
+                resume_token: Arc::new(Mutex::new(None)),
+            };
+            Self::connect(
+                sc.ws.clone(),
+                sc.message_queue.clone(),
+                sc.resume_token.clone(),
+            );
+            sc
+        }
+    }
+
+    /// How far behind the Server entities are rendered, in seconds.
+    /// This gives the next UpdatePosition time to arrive before it's needed,
+    /// so that there's almost always something to interpolate towards.
+    const INTERPOLATION_DELAY: f64 = 0.1;
+
+    /// How much each Pong moves the ServerClock; lower is smoother.
+    const CLOCK_SMOOTHING: f64 = 0.1;
+
+    /// This keeps track of roughly what tick the Server is on right now,
+    /// judging by the Snapshots it's been sending us and how long our Pings take.
+    pub struct ServerClock {
+        /// How far ahead of our clock the Server's is, in seconds.
+        offset: Option<f64>,
+        /// How long it takes a Snapshot to get here, in seconds.
+        /// This is None until we've heard back from one of our Pings.
+        one_way: Option<f64>,
+        /// How many ticks the Server runs each second; it tells us this in its Welcome.
+        pub tick_rate: f32,
+    }
+    impl Default for ServerClock {
+        fn default() -> Self {
+            Self {
+                offset: None,
+                one_way: None,
+                tick_rate: Tick::PER_SECOND,
+            }
+        }
+    }
+    impl ServerClock {
+        /// Seconds since this page was loaded.
+        pub fn local_now() -> f64 {
+            stdweb::web::Date::now() / 1000.0
+        }
+
+        /// How far ahead of our clock the Server's is, in seconds, as best we can tell.
+        pub fn offset(&self) -> Option<f64> {
+            self.offset
+        }
+
+        /// How long the Server has been running, as best we can tell.
+        pub fn now(&self) -> Option<f64> {
+            self.offset.map(|offset| Self::local_now() + offset)
+        }
+
+        /// When the Snapshots arriving about now were sent, on the Server's clock.
+        pub fn latest(&self) -> Option<f64> {
+            self.now().map(|now| now - self.one_way.unwrap_or(0.0))
+        }
+
+        /// Call this when the Pong for one of our Pings comes back in the Snapshot
+        /// for this tick, after this long. The Server answers Pings right away,
+        /// so (if it takes as long to get there as it does to get back)
+        /// it was sent about half that long ago.
+        pub fn sync(&mut self, tick: Tick, rtt: f64) {
+            let one_way = rtt / 2.0;
+            let offset = tick.seconds(self.tick_rate) + one_way - Self::local_now();
+            // a single slow Pong shouldn't throw everything off.
+            let (offset, one_way) = match (self.offset, self.one_way) {
+                (Some(old_offset), Some(old_one_way)) => (
+                    old_offset + (offset - old_offset) * CLOCK_SMOOTHING,
+                    old_one_way + (one_way - old_one_way) * CLOCK_SMOOTHING,
+                ),
+                _ => (offset, one_way),
+            };
+            self.offset = Some(offset);
+            self.one_way = Some(one_way);
+        }
+
+        /// Call this when a Snapshot for this tick arrives.
+        /// This is only used to guess at the Server's clock
+        /// until we've heard back from one of our Pings.
+        pub fn observe(&mut self, tick: Tick) {
+            if self.one_way.is_some() {
+                return;
+            }
+
+            let offset = tick.seconds(self.tick_rate) - Self::local_now();
+            self.offset = Some(match self.offset {
+                // Snapshots that show up quickly tell us the most about
+                // what time it is on the Server, so those are trusted right away,
+                // but the slower ones can only nudge the estimate back,
+                // in case the clocks are drifting apart.
+                Some(old) if old > offset => old + (offset - old) * 0.01,
+                _ => offset,
+            });
+        }
+    }
+
+    #[derive(Component, Default, Debug)]
+    /// The positions the Server has sent for an entity, and when it had them there.
+    /// Oldest first.
+    pub struct PositionBuffer(VecDeque<(f64, Iso2)>);
+    impl PositionBuffer {
+        pub fn push(&mut self, tick: Tick, clock: &ServerClock, iso: Iso2) {
+            let time = tick.seconds(clock.tick_rate);
+
+            // The Server only sends positions for things that are moving,
+            // so if there's a gap before this one, the entity was standing still
+            // until the tick before this one.
+            if let Some((last_time, last_iso)) = self.0.back().cloned() {
+                let still_until = time - 1.0 / clock.tick_rate as f64;
+                if still_until > last_time {
+                    self.0.push_back((still_until, last_iso));
+                }
+            }
+
+            self.0.push_back((time, iso));
+        }
+
+        /// Where the entity was at the given time,
+        /// or as close as we can get if we don't have a position for then.
+        /// Positions that won't be needed anymore are forgotten.
+        fn at(&mut self, time: f64) -> Option<Iso2> {
+            // throw out samples that are too old to ever be interpolated from again
+            while self.0.len() > 1 && self.0[1].0 <= time {
+                self.0.pop_front();
+            }
+
+            match (self.0.get(0), self.0.get(1)) {
+                (Some((from_time, from)), Some((to_time, to))) if *from_time <= time => {
+                    let t = ((time - from_time) / (to_time - from_time)) as f32;
+                    let translation = from.translation.vector.lerp(&to.translation.vector, t);
+                    let angle = from.rotation.angle() + from.rotation.angle_to(&to.rotation) * t;
+                    Some(Iso2::new(translation, angle))
+                }
+                (Some((_, first)), _) => Some(first.clone()),
+                _ => None,
+            }
+        }
+    }
+
+    /// Puts every entity where the Server had it INTERPOLATION_DELAY before
+    /// the positions arriving now were sent, by interpolating between
+    /// the positions the Server sent from around then.
+    /// Our own player is the exception; see `controls::Prediction`.
     pub struct SyncPositions;
     impl<'a> System<'a> for SyncPositions {
-        type SystemData = (WriteStorage<'a, Pos>, ReadStorage<'a, UpdatePosition>);
-
-        // the idea here is to get wherever the client thinks something is to where the server has
-        // it at within 10 ms.
-        // You want to do that transition gradually to avoid sudden jerking.
-        // If the internet is being slow and the update is from a while ago, however, it's probably
-        // more apt to just rely on the physics simulation on the client than on the last position
-        // the server sent; that way things in the simulation will still move.
-        fn run(&mut self, (mut currents, updates): Self::SystemData) {
-            for (
-                Pos(Iso2 {
-                    translation: at, ..
-                }),
-                UpdatePosition {
-                    iso: Iso2 {
-                        translation: go, ..
-                    },
-                    ..
-                },
-            ) in (&mut currents, &updates).join()
-            {
-                /*
-                const LERP_DIST: f32 = 0.03;
-                let to_go = go.vector - at.vector;
-
-                if to_go.magnitude().abs() > 2.0 * LERP_DIST {
-                    at.vector += to_go.normalize() * LERP_DIST;
-                } */
-                at.vector = at.vector.lerp(&go.vector, 0.03);
-                /*
-                current.rotation = na::UnitComplex::from_complex(
-                    current.rotation.complex()
-                        + current.rotation.rotation_to(&update.rotation).complex() * 0.06,
-                );*/
+        type SystemData = (
+            Entities<'a>,
+            Read<'a, ServerClock>,
+            Read<'a, Player>,
+            Write<'a, Prediction>,
+            WriteStorage<'a, Pos>,
+            WriteStorage<'a, PositionBuffer>,
+        );
+
+        fn run(
+            &mut self,
+            (ents, clock, player, mut prediction, mut poses, mut buffers): Self::SystemData,
+        ) {
+            if let Some(Pos(iso)) = player.0.and_then(|player| poses.get_mut(player)) {
+                if let Some(predicted) = prediction.reconcile() {
+                    *iso = predicted;
+                }
+            }
+
+            let render_time = match clock.latest() {
+                Some(now) => now - INTERPOLATION_DELAY,
+                None => return,
+            };
+
+            for (ent, Pos(iso), buffer) in (&*ents, &mut poses, &mut buffers).join() {
+                // Our own player is predicted, rather than interpolated.
+                if player.0 == Some(ent) {
+                    continue;
+                }
+
+                if let Some(interpolated) = buffer.at(render_time) {
+                    *iso = interpolated;
+                }
             }
         }
     }
 
     #[derive(Default)]
-    pub struct ServerToLocalIds(pub BiMap<u32, u32>);
+    pub struct ServerToLocalIds(pub BiMap<NetId, Entity>);
+    impl ServerToLocalIds {
+        /// Returns the local entity for the given NetId,
+        /// so long as that entity is still alive.
+        pub fn living(&self, server: NetId, ents: &Entities) -> Option<Entity> {
+            self.0.get_by_left(&server).cloned().filter(|ent| {
+                if !ents.is_alive(*ent) {
+                    info!("filtering out dead ent");
+                }
+                ents.is_alive(*ent)
+            })
+        }
+    }
 
     #[derive(Default)]
-    pub struct HandleServerPackets {
-        pub connection_established: bool,
-    }
+    pub struct HandleServerPackets;
     impl<'a> System<'a> for HandleServerPackets {
         type SystemData = (
             Entities<'a>,
@@ -318,59 +532,179 @@ mod net {
             Write<'a, Player>,
             Read<'a, LazyUpdate>,
             Read<'a, ServerConnection>,
+            Write<'a, Tick>,
+            Write<'a, ServerClock>,
+            Write<'a, Prediction>,
+            Write<'a, TileMap>,
+            Write<'a, Latency>,
+            Write<'a, crate::chat::ChatLog>,
+            WriteStorage<'a, PositionBuffer>,
         );
 
-        fn run(&mut self, (ents, mut server_to_local_ids, mut player, lu, sc): Self::SystemData) {
-            if let Ok(mut msgs) = sc.message_queue.try_lock() {
-                for msg in msgs.drain(0..) {
-                    // you know the connection is established when
-                    // we first get a message.
-                    if !self.connection_established {
-                        // immediately request to be put in the game
-                        // (later on we might want to have this happen
-                        //  after i.e. a menu is clicked through)
-                        sc.insert_comp(comn::net::SpawnPlayer);
-                        self.connection_established = true;
-                    }
-
-                    use NetMessage::*;
+        fn run(
+            &mut self,
+            (
+                ents,
+                mut server_to_local_ids,
+                mut player,
+                lu,
+                sc,
+                mut server_tick,
+                mut clock,
+                mut prediction,
+                mut tiles,
+                mut latency,
+                mut chat_log,
+                mut buffers,
+            ): Self::SystemData,
+        ) {
+            if let Ok(mut snapshots) = sc.message_queue.try_lock() {
+                for Snapshot { tick, messages } in snapshots.drain(0..) {
+                    // everything in a snapshot is applied together,
+                    // so the world is never left halfway through a tick.
+                    for msg in messages {
+                        use ServerMessage::*;
+
+                        match msg {
+                            Welcome {
+                                your_net_id,
+                                tick_rate,
+                                server_name,
+                                world_seed,
+                                resume_token,
+                                resumed,
+                            } => {
+                                info!(
+                                    "Welcomed to {} as {:?}, {}",
+                                    server_name,
+                                    your_net_id,
+                                    match world_seed {
+                                        Some(seed) => format!("world seed {}", seed),
+                                        None => "world loaded from a map".to_string(),
+                                    }
+                                );
+                                *sc.resume_token.lock().expect("The resume token is locked!") =
+                                    Some(resume_token);
 
-                    match msg {
-                        NewEnt(server) => {
-                            let local: u32 = ents.create().id();
-                            server_to_local_ids.0.insert(server, local);
-                        }
-                        InsertComp(id, net_comp) => {
-                            let ent = server_to_local_ids
-                                .0
-                                .get_by_left(&id)
-                                .map(|ent| ents.entity(*ent))
-                                .filter(|ent| {
-                                    if !ents.is_alive(*ent) {
-                                        info!("filtering out dead ent");
+                                // whatever we knew from before we lost the Server
+                                // is about to be sent again, or is gone for good.
+                                for (_, ent) in server_to_local_ids.0.iter() {
+                                    ents.delete(*ent).expect("Couldn't delete forgotten entity");
+                                }
+                                server_to_local_ids.0.clear();
+                                player.0 = None;
+                                *prediction = Prediction::default();
+                                *latency = Latency::default();
+                                *clock = ServerClock {
+                                    tick_rate,
+                                    ..ServerClock::default()
+                                };
+
+                                // we have to log in before we can be put in the game,
+                                // unless we're getting back the player we had before.
+                                if !resumed {
+                                    sc.log_in();
+                                }
+                            }
+                            Rejected(reason) => {
+                                error!("The Server turned us away: {}", reason);
+                                js! { alert(@{reason}); }
+                            }
+                            LoggedIn(username) => {
+                                info!("Logged in as {}", username);
+                                // immediately request to be put in the game
+                                // (later on we might want to have this happen
+                                //  after i.e. a menu is clicked through)
+                                sc.send(ClientMessage::SpawnPlayer);
+                            }
+                            LoginFailed(reason) => {
+                                warn!("Couldn't log in: {}", reason);
+                                js! { alert(@{reason}); }
+                                sc.log_in();
+                            }
+                            NewEnt(server) => {
+                                server_to_local_ids.0.insert(server, ents.create());
+                            }
+                            InsertComp(id, net_comp) => {
+                                if let Some(ent) = server_to_local_ids.living(id, &ents) {
+                                    match net_comp {
+                                        // this only shows up once we've spawned,
+                                        // which is when there's something to control.
+                                        NetComponent::LocalPlayer(_) => {
+                                            player.0 = Some(ent);
+                                        }
+                                        // the ones for our own player tell us how our
+                                        // predictions for it are holding up,
+                                        NetComponent::UpdatePosition(UpdatePosition {
+                                            iso,
+                                            ack: Some(ack),
+                                        }) if player.0 == Some(ent) => {
+                                            prediction.acknowledge(ack, iso)
+                                        }
+                                        // and the rest are collected up for SyncPositions
+                                        // to interpolate between, rather than inserted.
+                                        NetComponent::UpdatePosition(update) => buffers
+                                            .entry(ent)
+                                            .expect("Couldn't get PositionBuffer entry")
+                                            .or_insert_with(PositionBuffer::default)
+                                            .push(tick, &clock, update.iso),
+                                        _ => net_comp.insert(ent, &lu),
                                     }
-                                    ents.is_alive(*ent)
-                                });
-
-                            if let Some(ent) = ent {
-                                match net_comp {
-                                    // I should really have some sort of
-                                    // Establishment packet that deals with this.
-                                    NetComponent::LocalPlayer(_) => {
-                                        player.0 = Some(ent);
+                                } else {
+                                    error!(
+                                        "Can't insert component for dead entity, component: {:?}",
+                                        net_comp
+                                    );
+                                }
+                            }
+                            RemoveEnt(id) => {
+                                if let Some(ent) = server_to_local_ids.living(id, &ents) {
+                                    if player.0 == Some(ent) {
+                                        player.0 = None;
                                     }
-                                    _ => net_comp.insert(ent, &lu),
+                                    ents.delete(ent).expect("Couldn't delete removed entity");
                                 }
-                            } else {
-                                error!(
-                                    "Can't insert component for dead entity, component: {:?}",
-                                    net_comp
-                                );
+                                server_to_local_ids.0.remove_by_left(&id);
                             }
+                            RemoveComp(id, kind) => {
+                                if let Some(ent) = server_to_local_ids.living(id, &ents) {
+                                    kind.remove(ent, &lu);
+                                } else {
+                                    error!(
+                                        "Can't remove component for dead entity, kind: {:?}",
+                                        kind
+                                    );
+                                }
+                            }
+                            TileMap(map) => {
+                                info!("Got a {}x{} TileMap", map.width(), map.height());
+                                *tiles = map;
+                            }
+                            SetTile(cell, tile) => {
+                                if tiles.contains(cell) {
+                                    tiles.set(cell, tile)
+                                } else {
+                                    error!("Can't set tile off of the TileMap at {:?}", cell);
+                                }
+                            }
+                            Ping(id) => sc.send(ClientMessage::Pong(id)),
+                            Pong(id) => {
+                                if let Some(rtt) = latency.pong(id, ServerClock::local_now()) {
+                                    clock.sync(tick, rtt);
+                                }
+                            }
+                            Chat { from, text } => chat_log.0.push((from, text)),
                         }
                     }
+
+                    *server_tick = tick;
+                    clock.observe(tick);
                 }
             }
+
+            if let Some(id) = latency.ping(ServerClock::local_now()) {
+                sc.send(ClientMessage::Ping(id));
+            }
         }
     }
 }
@@ -378,9 +712,10 @@ mod net {
 mod controls {
     use super::net::ServerConnection;
     use crate::prelude::*;
-    use comn::controls::Heading;
+    use comn::controls::{step, Heading, Input, INPUTS_PER_SECOND};
+    use comn::ClientMessage;
     use std::{
-        collections::HashMap,
+        collections::{HashMap, VecDeque},
         sync::{Arc, Mutex},
     };
     use stdweb::{
@@ -395,9 +730,49 @@ mod controls {
     //(key direction, key down)
     type KeyMap = Arc<Mutex<HashMap<char, bool>>>;
 
+    /// How far behind the Client's input sampling is allowed to fall
+    /// (i.e. when the tab is in the background) before it gives up catching up.
+    const MAX_INPUT_LAG: f64 = 0.25;
+
+    #[derive(Default)]
+    /// This keeps track of the Inputs we've sent the Server,
+    /// so that we can move our player right away, and then fix that up
+    /// if the Server ends up putting them somewhere else.
+    pub struct Prediction {
+        /// The Inputs the Server hasn't said it's applied yet, oldest first.
+        unacked: VecDeque<Input>,
+        /// The last position the Server told us our player was at,
+        /// and the seq of the last Input it had applied to get them there.
+        authoritative: Option<(u32, Iso2)>,
+    }
+    impl Prediction {
+        pub fn acknowledge(&mut self, ack: u32, iso: Iso2) {
+            self.authoritative = Some((ack, iso));
+        }
+
+        /// If the Server's told us where our player is since this was last called,
+        /// returns where they are now, by reapplying all of the Inputs the Server
+        /// hadn't applied yet on top of where the Server had them.
+        pub fn reconcile(&mut self) -> Option<Iso2> {
+            let (ack, mut iso) = self.authoritative.take()?;
+
+            while self.unacked.front().map_or(false, |input| input.seq <= ack) {
+                self.unacked.pop_front();
+            }
+            for Input { heading, .. } in self.unacked.iter() {
+                step(&mut iso, &heading.dir, 1.0 / INPUTS_PER_SECOND);
+            }
+
+            Some(iso)
+        }
+    }
+
     pub struct MovementControl {
         keys: KeyMap,
         current_heading: Vec2,
+        /// When the last Input was sampled, in seconds.
+        last_input: Option<f64>,
+        next_seq: u32,
     }
     impl MovementControl {
         fn handle_key_event<K: IKeyboardEvent + ConcreteEvent>(keys: KeyMap, key_down: bool) {
@@ -423,6 +798,8 @@ mod controls {
             Self {
                 keys,
                 current_heading: na::zero(),
+                last_input: None,
+                next_seq: 0,
             }
         }
     }
@@ -430,37 +807,61 @@ mod controls {
         type SystemData = (
             Read<'a, ServerConnection>,
             Read<'a, Player>,
+            Write<'a, Prediction>,
             WriteStorage<'a, Heading>,
+            WriteStorage<'a, Pos>,
         );
 
-        fn run(&mut self, (sc, player, mut headings): Self::SystemData) {
+        fn run(&mut self, (sc, player, mut prediction, mut headings, mut poses): Self::SystemData) {
+            let now = stdweb::web::Date::now() / 1000.0;
+            let input_interval = 1.0 / INPUTS_PER_SECOND as f64;
+            let mut last_input = self.last_input.unwrap_or(now).max(now - MAX_INPUT_LAG);
+            self.last_input = Some(last_input);
+
             // if keys isn't being used by the listener, and the player character has been added.
             if let (Ok(keys), Some(player)) = (self.keys.try_lock(), player.0) {
                 // these variables are needed to determine direction from key names.
-                if keys.len() > 0 {
-                    let move_vec = keys.iter().fold(na::zero(), |vec: Vec2, key| match key {
-                        ('w', true) => vec - Vec2::y(),
-                        ('s', true) => vec + Vec2::y(),
-                        ('a', true) => vec - Vec2::x(),
-                        ('d', true) => vec + Vec2::x(),
-                        _ => vec,
-                    });
+                let move_vec = keys.iter().fold(na::zero(), |vec: Vec2, key| match key {
+                    ('w', true) => vec - Vec2::y(),
+                    ('s', true) => vec + Vec2::y(),
+                    ('a', true) => vec - Vec2::x(),
+                    ('d', true) => vec + Vec2::x(),
+                    _ => vec,
+                });
+                let heading = Heading {
+                    dir: na::Unit::new_normalize(move_vec),
+                };
 
-                    if move_vec != self.current_heading {
-                        self.current_heading = move_vec;
-                        let heading = Heading {
-                            dir: na::Unit::new_normalize(move_vec),
-                        };
+                while now - last_input >= input_interval {
+                    last_input += input_interval;
 
-                        // now that we know, tell the server where we'd like to go
-                        sc.insert_comp(heading.clone());
+                    // no sense sending a bunch of Inputs that all say to stand still.
+                    if move_vec == na::zero() && self.current_heading == na::zero() {
+                        continue;
+                    }
+                    self.current_heading = move_vec;
 
-                        // and record that locally for clientside prediction
-                        headings.insert(player, heading.clone()).expect(
-                            "couldn't insert heading to player for clientside movement prediction",
-                        );
+                    let input = Input {
+                        seq: self.next_seq,
+                        heading: heading.clone(),
+                    };
+                    self.next_seq += 1;
+
+                    // now that we know, tell the server where we'd like to go
+                    sc.send(ClientMessage::Input(input.clone()));
+
+                    // and go there ourselves right away, in the same way the Server will
+                    if let Some(Pos(iso)) = poses.get_mut(player) {
+                        step(iso, &heading.dir, 1.0 / INPUTS_PER_SECOND);
                     }
+                    prediction.unacked.push_back(input);
                 }
+                self.last_input = Some(last_input);
+
+                // this is what the player's animation goes off of
+                headings
+                    .insert(player, heading)
+                    .expect("couldn't insert heading to player for clientside animation");
             }
         }
     }
@@ -571,11 +972,11 @@ mod controls {
                         .min_by(|(_, dist_a), (_, dist_b)| dist_a.partial_cmp(&dist_b).unwrap())
                         // we care about the item's id on the server, not its distance from the player.
                         .and_then(|(item_entity, _)| {
-                            server_to_local_ids.0.get_by_right(&item_entity.id())
+                            server_to_local_ids.0.get_by_right(&item_entity)
                         })
                     {
-                        trace!("sending request for picking up item with id {}", id);
-                        sc.insert_comp(PickupRequest { id });
+                        trace!("sending request for picking up item with id {:?}", id);
+                        sc.send(ClientMessage::PickupRequest(PickupRequest { id }));
                     }
                 }
             }
@@ -586,7 +987,8 @@ mod controls {
 mod item {
     use crate::prelude::*;
     use comn::art::Appearance;
-    use comn::item::{Deposition, DropRequest, Inventory, SlotIndex};
+    use comn::item::{DropRequest, Inventory, SlotIndex};
+    use comn::{ClientMessage, NetId};
 
     use std::collections::HashMap;
     use std::sync::{Arc, Mutex};
@@ -595,10 +997,10 @@ mod item {
     };
 
     pub struct UpdateInventory {
-        item_drop_events: Arc<Mutex<Vec<u32>>>,
+        item_drop_events: Arc<Mutex<Vec<NetId>>>,
         /// It's tricky for us to store entire SlotIndexes next to HTML5 elements,
         /// so we just use this little HashMap.
-        ents_to_slot: HashMap<u32, SlotIndex>,
+        ents_to_slot: HashMap<NetId, SlotIndex>,
     }
     impl Default for UpdateInventory {
         fn default() -> Self {
@@ -610,7 +1012,7 @@ mod item {
                     item_drop_events
                         .lock()
                         .expect("couldn't lock item drop events")
-                        .push(id as u32);
+                        .push(NetId(id as u32));
                 };
                 js! {
                     let drop_item = @{drop_item};
@@ -651,9 +1053,9 @@ mod item {
         ) {
             if let Ok(mut item_drops) = self.item_drop_events.lock() {
                 for ent_id in item_drops.drain(..) {
-                    sc.insert_comp(DropRequest {
+                    sc.send(ClientMessage::DropRequest(DropRequest {
                         item_index: self.ents_to_slot[&ent_id].clone(),
-                    });
+                    }));
                 }
             }
 
@@ -686,12 +1088,10 @@ mod item {
                     let image = slot
                         // be a non-empty slot and have an appearance,
                         .map(|item_server_id| {
-                            let item_ent = ents.entity(
-                                *server_to_local_ids
-                                    .0
-                                    .get_by_left(&item_server_id)
-                                    .expect("can't render item; invalid server id"),
-                            );
+                            let item_ent = *server_to_local_ids
+                                .0
+                                .get_by_left(&item_server_id)
+                                .expect("can't render item; invalid server id");
                             let appearance = appearances
                                 .get(item_ent)
                                 .expect("inventory item has no appearance");
@@ -724,7 +1124,7 @@ mod item {
                             if let Some(item) = slot {
                                 self.ents_to_slot.insert(*item, index.clone());
 
-                                new_img.set_attribute("id", &item.to_string()).unwrap();
+                                new_img.set_attribute("id", &item.0.to_string()).unwrap();
                             }
 
                             slot_div
@@ -757,7 +1157,7 @@ mod item {
                     // (ofc, we only want to do that if it's an item)
                     if let Some(item) = slot {
                         js! {
-                            $("#" + @{item}).draggable({
+                            $("#" + @{item.0}).draggable({
                                 revert: true
                             });
                         }
@@ -769,21 +1169,204 @@ mod item {
             }
         }
     }
+}
 
-    /// This system removes the Pos component from entities
-    /// with the comn::item::Deposition Component, making the entities
-    /// unable to exist physically, effectively turning them into items.
-    pub struct DepositionItems;
-    impl<'a> System<'a> for DepositionItems {
-        type SystemData = (
-            Entities<'a>,
-            WriteStorage<'a, Deposition>,
-            WriteStorage<'a, Pos>,
-        );
+mod chat {
+    use crate::net::ServerConnection;
+    use crate::prelude::*;
+    use comn::{net::MAX_CHAT_LENGTH, ClientMessage};
+    use std::sync::{Arc, Mutex};
+    use stdweb::{
+        traits::{IEvent, IKeyboardEvent},
+        unstable::TryInto,
+        web::{
+            document,
+            event::{KeyDownEvent, KeyPressEvent},
+            html_element::InputElement,
+            Element, IElement, IEventTarget, IHtmlElement, INode,
+        },
+    };
+
+    /// How many lines are kept in the chat log before the oldest are thrown away.
+    const MAX_LINES: usize = 100;
+
+    #[derive(Default)]
+    /// What's been said since ChatBox last ran, and who said it;
+    /// HandleServerPackets fills this, and ChatBox empties it.
+    /// Lines from nobody are from the Server itself.
+    pub struct ChatLog(pub Vec<(Option<String>, String)>);
+
+    /// Pressing Enter opens a box to type into, and pressing it again
+    /// sends what was typed off to the Server; see `comn::ClientMessage::Chat`.
+    /// Everything that's been said is shown above that box.
+    pub struct ChatBox {
+        log: Element,
+        typed: Arc<Mutex<Vec<String>>>,
+    }
+    impl Default for ChatBox {
+        fn default() -> Self {
+            let log = document().create_element("div").unwrap();
+            log.class_list().add("chat_log").unwrap();
+            document().body().unwrap().append_child(&log);
+
+            let input: InputElement = document()
+                .create_element("input")
+                .unwrap()
+                .try_into()
+                .expect("Couldn't make chat input");
+            input.class_list().add("chat_input").unwrap();
+            input
+                .set_attribute("maxlength", &MAX_CHAT_LENGTH.to_string())
+                .unwrap();
+            document().body().unwrap().append_child(&input);
+
+            // what's typed into the box shouldn't move the player around,
+            // but letting go of a key still should stop them, even if the
+            // box was opened while it was held down.
+            input.add_event_listener(|e: KeyPressEvent| e.stop_propagation());
+
+            let typed = Arc::new(Mutex::new(Vec::new()));
+            document().add_event_listener({
+                let (input, typed) = (input.clone(), typed.clone());
+
+                move |e: KeyDownEvent| {
+                    if e.key() != "Enter" {
+                        return;
+                    }
+                    e.prevent_default();
+
+                    let typing = js! { return document.activeElement === @{&input}; };
+                    if typing.try_into().unwrap_or(false) {
+                        let text = input.raw_value();
+                        input.set_raw_value("");
+                        input.blur();
+                        if !text.trim().is_empty() {
+                            typed.lock().expect("Can't lock typed chat").push(text);
+                        }
+                    } else {
+                        input.focus();
+                    }
+                }
+            });
+
+            Self { log, typed }
+        }
+    }
+    impl<'a> System<'a> for ChatBox {
+        type SystemData = (Read<'a, ServerConnection>, Write<'a, ChatLog>);
+
+        fn run(&mut self, (sc, mut chat_log): Self::SystemData) {
+            if let Ok(mut typed) = self.typed.try_lock() {
+                for text in typed.drain(..) {
+                    sc.send(ClientMessage::Chat(text));
+                }
+            }
+
+            for (from, text) in chat_log.0.drain(..) {
+                let line = document().create_element("div").unwrap();
+                match from {
+                    Some(from) => line.set_text_content(&format!("{}: {}", from, text)),
+                    None => {
+                        line.class_list().add("notice").unwrap();
+                        line.set_text_content(&text);
+                    }
+                }
+                self.log.append_child(&line);
+            }
+
+            while self.log.child_nodes().len() as usize > MAX_LINES {
+                if let Some(oldest) = self.log.first_child() {
+                    self.log
+                        .remove_child(&oldest)
+                        .expect("Couldn't remove old chat line");
+                }
+            }
+            if let Some(last) = self.log.last_child() {
+                js! { @{last}.scrollIntoView(); }
+            }
+        }
+    }
+}
+
+mod debug {
+    use crate::net::ServerClock;
+    use crate::prelude::*;
+    use comn::ping::Latency;
+    use comn::Tick;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use stdweb::{
+        traits::{IEvent, IKeyboardEvent},
+        web::{document, event::KeyDownEvent, Element, IElement, IEventTarget, INode},
+    };
+
+    /// Pressing this shows or hides the overlay.
+    const TOGGLE_KEY: &str = "F3";
+
+    /// Shows how things are going with the Server in the corner of the screen,
+    /// like how long our Pings take. It's hidden until TOGGLE_KEY is pressed.
+    pub struct DebugOverlay {
+        div: Element,
+        shown: Arc<AtomicBool>,
+        /// What's in the div right now, so it's only touched when that changes.
+        text: String,
+    }
+    impl Default for DebugOverlay {
+        fn default() -> Self {
+            let div = document().create_element("div").unwrap();
+            div.class_list().add("debug").unwrap();
+            document().body().unwrap().append_child(&div);
+
+            let shown = Arc::new(AtomicBool::new(false));
+            document().add_event_listener({
+                let (div, shown) = (div.clone(), shown.clone());
+
+                move |e: KeyDownEvent| {
+                    if e.key() == TOGGLE_KEY {
+                        e.prevent_default();
+                        let classes = div.class_list();
+                        if shown.fetch_xor(true, Ordering::SeqCst) {
+                            classes
+                                .remove("shown")
+                                .expect("Couldn't hide debug overlay");
+                        } else {
+                            classes.add("shown").expect("Couldn't show debug overlay");
+                        }
+                    }
+                }
+            });
+
+            Self {
+                div,
+                shown,
+                text: String::new(),
+            }
+        }
+    }
+    impl<'a> System<'a> for DebugOverlay {
+        type SystemData = (Read<'a, Latency>, Read<'a, ServerClock>, Read<'a, Tick>);
+
+        fn run(&mut self, (latency, clock, tick): Self::SystemData) {
+            if !self.shown.load(Ordering::SeqCst) {
+                return;
+            }
+
+            let seconds = |s: Option<f64>| match s {
+                Some(s) => format!("{:.1}ms", s * 1000.0),
+                None => "?".to_string(),
+            };
+            let text = format!(
+                "round trip: {}\nclock offset: {}\nserver tick: {}",
+                seconds(latency.rtt()),
+                seconds(clock.offset()),
+                tick.0,
+            );
 
-        fn run(&mut self, (ents, mut deposes, mut poses): Self::SystemData) {
-            for (ent, _) in (&*ents, deposes.drain()).join() {
-                poses.remove(ent).expect("Couldn't deposition entity");
+            if text != self.text {
+                self.div.set_text_content(&text);
+                self.text = text;
             }
         }
     }
@@ -814,11 +1397,14 @@ fn main() {
         .with(renderer::Render::default(),          "render",       &[])
         .with(comn::art::UpdateAnimations,          "animate",      &[])
         // util
-        .with(net::HandleServerPackets::default(),  "packets",      &[])
+        .with(net::HandleServerPackets,             "packets",      &[])
         .with(comn::dead::ClearDead,                "clear dead",   &[])
         // items
-        .with(item::DepositionItems,                "deposition",   &[])
         .with(item::UpdateInventory::default(),     "update items", &[])
+        // chat
+        .with(chat::ChatBox::default(),             "chat",         &["packets"])
+        // debug
+        .with(debug::DebugOverlay::default(),       "debug",        &[])
         .build();
 
     // go through all of the systems and register components and resources accordingly
@@ -5,13 +5,11 @@ use specs::{prelude::*, Component};
 pub mod player_anim;
 pub use player_anim::PlayerAnimationController;
 
-#[derive(Clone, Debug, Default, Component, Serialize, Deserialize)]
-/// Entities with this component are rendered at a special stage on the client,
-/// and their origin is in the (center, center) rather than their (center, bottom)
-#[storage(NullStorage)]
-pub struct Tile;
+pub mod tilemap;
+pub use tilemap::{TileMap, TILE_SIZE};
 
 #[derive(Clone, Debug, Default, Component, Serialize, Deserialize)]
+#[storage(FlaggedStorage)]
 /// Entities with this component are rendered at a special stage on the client,
 /// and their origin is in the (center, center) rather than their (center, bottom)
 pub struct Animate {
@@ -80,6 +78,7 @@ pub struct SpritesheetData {
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug, IntoEnumIterator, Component, Serialize, Deserialize)]
+#[storage(FlaggedStorage)]
 /// Behavior can affect how something is rendered on the client, but
 /// the appearance should never affect the behavior.
 /// Therefore, this component isn't really used on the server all that much
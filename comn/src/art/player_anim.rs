@@ -58,4 +58,5 @@ fn test_player_anim_indexing() {
 use serde::{Deserialize, Serialize};
 use specs::{prelude::*, Component};
 #[derive(Clone, Debug, Component, Serialize, Deserialize)]
+#[storage(FlaggedStorage)]
 pub struct PlayerAnimationController;
@@ -0,0 +1,236 @@
+//! The floor isn't made of entities; there are far too many tiles for that,
+//! and they hardly ever change. Instead, it's one grid of Appearances,
+//! which the Server sends to each Client in full when they join,
+//! and then one cell at a time whenever a tile changes.
+use super::Appearance;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+/// How far apart the centers of neighboring tiles are.
+pub const TILE_SIZE: f32 = 2.0;
+
+/// Where a tile is on a TileMap, as (column, row).
+pub type Cell = (usize, usize);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(try_from = "SentTileMap")]
+/// Tiles are drawn centered on `loc(cell)`, beneath everything else.
+pub struct TileMap {
+    width: usize,
+    height: usize,
+    /// Row after row; cells without a tile are None.
+    /// Most of the floor is made of long rows of the same few tiles,
+    /// so these are sent as runs.
+    #[serde(serialize_with = "runs::serialize")]
+    tiles: Vec<Option<Appearance>>,
+    #[serde(skip)]
+    /// The cells that have been set since `take_changes` was last called.
+    changed: BTreeSet<Cell>,
+}
+impl TileMap {
+    /// A map this big without any tiles in it.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![None; width * height],
+            changed: BTreeSet::new(),
+        }
+    }
+
+    /// A map this big with these tiles in it, row after row.
+    /// There has to be exactly one for each cell, or nothing's made.
+    pub fn with_tiles(
+        width: usize,
+        height: usize,
+        tiles: Vec<Option<Appearance>>,
+    ) -> Result<Self, String> {
+        match width.checked_mul(height) {
+            Some(cells) if cells == tiles.len() => Ok(Self {
+                width,
+                height,
+                tiles,
+                changed: BTreeSet::new(),
+            }),
+            _ => Err(format!(
+                "a {}x{} TileMap can't have {} tiles",
+                width,
+                height,
+                tiles.len()
+            )),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Where the center of this cell is, in the world.
+    pub fn loc((x, y): Cell) -> Vec2 {
+        Vec2::new(x as f32, y as f32) * TILE_SIZE
+    }
+
+    /// Whether or not this cell is on the map.
+    pub fn contains(&self, (x, y): Cell) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// The tile in this cell, if there is one.
+    /// Cells off of the map never have one.
+    pub fn get(&self, (x, y): Cell) -> Option<&Appearance> {
+        if self.contains((x, y)) {
+            self.tiles[y * self.width + x].as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Puts this tile in this cell, or clears it if None is given.
+    /// The cell has to be on the map; check with `contains` if it might not be.
+    pub fn set(&mut self, (x, y): Cell, tile: Option<Appearance>) {
+        assert!(
+            self.contains((x, y)),
+            "({}, {}) is off of a {}x{} TileMap",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+
+        let old = &mut self.tiles[y * self.width + x];
+        if *old != tile {
+            *old = tile;
+            self.changed.insert((x, y));
+        }
+    }
+
+    /// Every cell with a tile in it, and that tile.
+    pub fn tiles<'a>(&'a self) -> impl Iterator<Item = (Cell, &'a Appearance)> + 'a {
+        let width = self.width;
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, tile)| Some(((i % width, i / width), tile.as_ref()?)))
+    }
+
+    /// Every cell that's been set since this was last called, and what's there now.
+    pub fn take_changes(&mut self) -> Vec<(Cell, Option<Appearance>)> {
+        let changed = std::mem::replace(&mut self.changed, BTreeSet::new());
+        changed
+            .into_iter()
+            .map(|cell| (cell, self.get(cell).cloned()))
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+/// A TileMap as it's read, before it's been made sure
+/// that its tiles actually fill it; see `TileMap::with_tiles`.
+struct SentTileMap {
+    width: usize,
+    height: usize,
+    /// Still in runs; these aren't expanded until they've been
+    /// counted up, so a few huge runs can't use up all of our memory.
+    tiles: Vec<(u32, Option<Appearance>)>,
+}
+impl TryFrom<SentTileMap> for TileMap {
+    type Error = String;
+
+    fn try_from(sent: SentTileMap) -> Result<Self, String> {
+        let cells = sent.width.checked_mul(sent.height);
+        let count = sent.tiles.iter().try_fold(0usize, |total, (count, _)| {
+            total.checked_add(*count as usize)
+        });
+        if count.is_none() || count != cells {
+            return Err(format!(
+                "a {}x{} TileMap can't have {} tiles",
+                sent.width,
+                sent.height,
+                count.map_or("that many".to_string(), |count| count.to_string())
+            ));
+        }
+        Self::with_tiles(sent.width, sent.height, runs::decompress(sent.tiles))
+    }
+}
+
+/// Sends a TileMap's tiles as (how many in a row, which tile) pairs.
+mod runs {
+    use super::Appearance;
+    use serde::{Serialize, Serializer};
+
+    pub fn compress(tiles: &[Option<Appearance>]) -> Vec<(u32, Option<Appearance>)> {
+        let mut runs: Vec<(u32, Option<Appearance>)> = Vec::new();
+        for tile in tiles {
+            match runs.last_mut() {
+                Some((count, last)) if last == tile => *count += 1,
+                _ => runs.push((1, tile.clone())),
+            }
+        }
+        runs
+    }
+
+    pub fn decompress(runs: Vec<(u32, Option<Appearance>)>) -> Vec<Option<Appearance>> {
+        runs.into_iter()
+            .flat_map(|(count, tile)| std::iter::repeat(tile).take(count as usize))
+            .collect()
+    }
+
+    pub fn serialize<S: Serializer>(
+        tiles: &[Option<Appearance>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        compress(tiles).serialize(serializer)
+    }
+}
+
+#[test]
+fn tiles_are_sent_as_runs() {
+    use Appearance::*;
+    let mut map = TileMap::new(4, 3);
+    for x in 0..4 {
+        map.set((x, 1), Some(Rock));
+    }
+    map.set((2, 2), Some(RockHole));
+
+    let runs = runs::compress(&map.tiles);
+    assert_eq!(runs.len(), 5);
+    assert_eq!(runs::decompress(runs), map.tiles);
+}
+
+#[test]
+fn short_maps_are_turned_away() {
+    use Appearance::*;
+    let sent = |tiles| SentTileMap {
+        width: 3,
+        height: 3,
+        tiles,
+    };
+
+    assert!(TileMap::try_from(sent(vec![(4, None), (1, Some(Rock)), (4, None)])).is_ok());
+    assert!(TileMap::try_from(sent(vec![(4, None), (1, Some(Rock)), (3, None)])).is_err());
+    // these would take up far more memory than we have, if they were ever expanded.
+    assert!(TileMap::try_from(sent(vec![(u32::MAX, None); 3])).is_err());
+    assert!(TileMap::with_tiles(usize::MAX, 2, vec![]).is_err());
+}
+
+#[test]
+fn only_real_changes_are_taken() {
+    use Appearance::*;
+    let mut map = TileMap::new(2, 2);
+    map.set((0, 0), Some(Rock));
+    map.set((1, 1), None);
+    map.set((1, 0), Some(Rock));
+    map.set((1, 0), Some(SpottedRock));
+
+    assert_eq!(
+        map.take_changes(),
+        vec![((0, 0), Some(Rock)), ((1, 0), Some(SpottedRock))]
+    );
+    assert!(map.take_changes().is_empty());
+}
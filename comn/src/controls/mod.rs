@@ -1,9 +1,17 @@
 use super::{na, Vec2};
 use serde::{Deserialize, Serialize};
 use specs::{prelude::*, Component};
+use std::collections::VecDeque;
 
 pub mod movement;
-pub use movement::MoveHeadings;
+pub use movement::{step, MoveHeadings};
+
+/// How many Inputs the Client samples and sends to the Server each second.
+pub const INPUTS_PER_SECOND: f32 = 60.0;
+
+/// How many seconds' worth of Inputs the Server lets a player save up,
+/// so that they can catch up if a few of their Inputs arrive late.
+pub const CATCH_UP: f32 = 0.1;
 
 #[derive(Clone, Debug, Component, Serialize, Deserialize)]
 /// Nobody gets these on the Server, but the Server
@@ -18,3 +26,97 @@ pub struct Camera;
 pub struct Heading {
     pub dir: na::Unit<Vec2>,
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// The Client samples its player's Heading INPUTS_PER_SECOND times a second,
+/// numbers each sample, and sends it along to the Server as one of these.
+/// Each Input moves the player for 1 / INPUTS_PER_SECOND seconds.
+pub struct Input {
+    pub seq: u32,
+    pub heading: Heading,
+}
+
+#[derive(Clone, Debug, Default, Component)]
+/// The Server keeps the Inputs it hasn't applied yet in one of these,
+/// on the entity of the player that sent them.
+pub struct InputQueue {
+    pending: VecDeque<Input>,
+    /// The seq of the last Input that was applied,
+    /// which is sent back to the Client so it knows where the Server's at.
+    pub last_applied: Option<u32>,
+    /// How many more Inputs can be applied; see `earn`.
+    budget: f32,
+}
+impl InputQueue {
+    /// No more than a second's worth of Inputs are held on to;
+    /// any more than that are thrown out.
+    pub fn push(&mut self, input: Input) {
+        if self.pending.len() < INPUTS_PER_SECOND as usize {
+            self.pending.push_back(input);
+        }
+    }
+
+    /// Lets `dt` more seconds' worth of Inputs be popped.
+    /// Whatever isn't used is saved, but only up to CATCH_UP seconds' worth,
+    /// so nobody can go any faster than the clock by sending more Inputs;
+    /// catching up on late ones only spends what they didn't use while waiting.
+    pub fn earn(&mut self, dt: f32) {
+        let most = (CATCH_UP + dt) * INPUTS_PER_SECOND;
+        self.budget = (self.budget + dt * INPUTS_PER_SECOND).min(most);
+    }
+
+    /// Takes the oldest Input out of the queue, remembering its seq,
+    /// if there's enough budget left to apply it.
+    pub fn pop(&mut self) -> Option<Input> {
+        if self.budget < 1.0 {
+            return None;
+        }
+        let input = self.pending.pop_front()?;
+        self.budget -= 1.0;
+        self.last_applied = Some(input.seq);
+        Some(input)
+    }
+}
+
+#[test]
+fn inputs_cant_outrun_the_clock() {
+    let input = |seq| Input {
+        seq,
+        heading: Heading {
+            dir: na::Unit::new_normalize(Vec2::x()),
+        },
+    };
+    let dt = 1.0 / 20.0;
+    let per_tick = (INPUTS_PER_SECOND * dt) as usize;
+    let mut queue = InputQueue::default();
+
+    // twice as many Inputs as there should be still only get applied as fast as the clock
+    let mut applied = 0;
+    let mut seq = 0;
+    for _ in 0..20 {
+        for _ in 0..per_tick * 2 {
+            queue.push(input(seq));
+            seq += 1;
+        }
+        queue.earn(dt);
+        while queue.pop().is_some() {
+            applied += 1;
+        }
+    }
+    assert_eq!(applied, per_tick * 20);
+
+    // but after waiting a while, a few late ones can be caught up on all at once.
+    let mut queue = InputQueue::default();
+    for _ in 0..10 {
+        queue.earn(dt);
+    }
+    for seq in 0..INPUTS_PER_SECOND as u32 {
+        queue.push(input(seq));
+    }
+    queue.earn(dt);
+    let mut caught_up = 0;
+    while queue.pop().is_some() {
+        caught_up += 1;
+    }
+    assert_eq!(caught_up, ((CATCH_UP + dt) * INPUTS_PER_SECOND) as usize);
+}
@@ -1,56 +1,81 @@
 use crate::{
     art::{player_anim::PlayerAnimation, Animate, PlayerAnimationController},
-    controls::Heading,
+    controls::{Heading, InputQueue, INPUTS_PER_SECOND},
     prelude::*,
     Fps,
 };
 use specs::prelude::*;
 
+/// How far something with a Heading moves each second.
+pub const SPEED: f32 = 8.1;
+
+/// Moves `iso` as far as something going in `dir` would get in `dt` seconds.
+/// The Client and the Server both move players with this,
+/// so that they agree on where each Input takes them.
+pub fn step(iso: &mut Iso2, dir: &na::Unit<Vec2>, dt: f32) {
+    if dir.magnitude() > 0.0 {
+        iso.translation.vector += dir.into_inner() * SPEED * dt;
+    }
+}
+
+/// Applies the Inputs waiting in each InputQueue,
+/// and animates everything with a Heading to match it.
 pub struct MoveHeadings;
 impl<'a> System<'a> for MoveHeadings {
     type SystemData = (
         Read<'a, Fps>,
+        Entities<'a>,
         WriteStorage<'a, Pos>,
         WriteStorage<'a, Heading>,
+        WriteStorage<'a, InputQueue>,
         WriteStorage<'a, Animate>,
         ReadStorage<'a, PlayerAnimationController>,
     );
 
-    fn run(&mut self, (fps, mut isos, mut heads, mut animates, anim_controls): Self::SystemData) {
-        for (iso, &mut Heading { mut dir }, player_anim_control, animaybe) in (
-            &mut isos,
-            &mut heads,
-            anim_controls.maybe(),
-            (&mut animates).maybe(),
-        )
-            .join()
-        {
+    fn run(
+        &mut self,
+        (fps, ents, mut isos, mut heads, mut queues, mut animates, anim_controls): Self::SystemData,
+    ) {
+        // A client shouldn't be able to go any faster by sending more Inputs,
+        // but they should be able to catch up a bit if a few arrive late;
+        // InputQueue::earn keeps track of how many they've got coming.
+        for (ent, Pos(iso), queue) in (&*ents, &mut isos, &mut queues).join() {
+            queue.earn(1.0 / fps.0);
+            while let Some(input) = queue.pop() {
+                step(iso, &input.heading.dir, 1.0 / INPUTS_PER_SECOND);
+                heads
+                    .insert(ent, input.heading)
+                    .expect("Couldn't insert Heading from Input");
+            }
+        }
+
+        // Animates are only touched if they actually need to change,
+        // because otherwise they'd be flagged as modified every tick and
+        // the Server would keep sending them to everyone.
+        for (ent, &Heading { mut dir }, _) in (&*ents, &heads, &anim_controls).join() {
             if dir.magnitude() > 0.0 {
+                use crate::art::player_anim::Direction::*;
                 dir.renormalize();
 
-                // 20 fps = 3, 60 fps = 1
-                let update_granularity = 1.0 / fps.0 * 60.0;
-                iso.0.translation.vector += dir.into_inner() * 0.135 * update_granularity;
-
-                if let (true, Some(anim)) = (player_anim_control.is_some(), animaybe) {
-                    use crate::art::player_anim::Direction::*;
+                let direction = if dir.x > 0.0 {
+                    Right
+                } else if dir.x < 0.0 {
+                    Left
+                } else if dir.y > 0.0 {
+                    Down
+                } else {
+                    Up
+                };
 
-                    let direction = if dir.x > 0.0 {
-                        Right
-                    } else if dir.x < 0.0 {
-                        Left
-                    } else if dir.y > 0.0 {
-                        Down
-                    } else {
-                        Up
-                    };
-
-                    anim.row = PlayerAnimation::Walk(direction).into();
-                }
-            } else {
-                if let (true, Some(anim)) = (player_anim_control.is_some(), animaybe) {
-                    anim.current_frame = 0;
+                let row = PlayerAnimation::Walk(direction).into();
+                if animates.get(ent).map_or(false, |anim| anim.row != row) {
+                    animates.get_mut(ent).unwrap().row = row;
                 }
+            } else if animates
+                .get(ent)
+                .map_or(false, |anim| anim.current_frame != 0)
+            {
+                animates.get_mut(ent).unwrap().current_frame = 0;
             }
         }
     }
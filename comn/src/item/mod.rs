@@ -1,9 +1,11 @@
+use crate::net::NetId;
 use crate::prelude::*;
 use serde::{Deserialize, Serialize};
 use specs::{prelude::*, Component};
 use std::collections::HashMap;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Component, Serialize, Deserialize)]
+#[storage(FlaggedStorage)]
 /// Something that can be put inside of an inventory.
 pub enum Item {
     /// An Item of this variant should also have a Weapon component.
@@ -23,8 +25,8 @@ impl Default for Item {
 
 #[derive(Clone, Debug, Default, Component, Serialize, Deserialize)]
 pub struct PickupRequest {
-    /// The id of the Entity the Player would like to put in their inventory.
-    pub id: u32,
+    /// The NetId of the Entity the Player would like to put in their inventory.
+    pub id: NetId,
 }
 
 #[derive(Clone, Debug, Default, Component, Serialize, Deserialize)]
@@ -32,7 +34,7 @@ pub struct PickupRequest {
 /// item's location in the player's inventory, not that item's index in the ECS.
 ///
 /// There are a couple of reasons for this. First of all, this acts as a security measure;
-/// if raw ids were accepted here, players could potentially drop items that are actually
+/// if NetIds were accepted here, players could potentially drop items that are actually
 /// in other people's inventories.
 ///
 /// Such a vulnerability could be avoided with a couple of preemptive checks,
@@ -59,11 +61,12 @@ pub enum Error {
 }
 
 #[derive(Clone, Debug, Default, Component, Serialize, Deserialize)]
+#[storage(FlaggedStorage)]
 /// #Overview
-/// This Component stores the server ids of all of the items which are owned by
+/// This Component stores the NetIds of all of the items which are owned by
 /// the entity with which this Component is associated.
 /// Individual items are referred to using Slots.
-/// Inside of a Slot, the u32 of an entity may or may not be stored.
+/// Inside of a Slot, the NetId of an entity may or may not be stored.
 ///
 /// #Anatomy of an Inventory
 /// Conceptually, an inventory is comprised of two parts:
@@ -78,7 +81,7 @@ pub enum Error {
 /// in which case the reserved slot should take priority.
 pub struct Inventory {
     // The internal representation of the Inventory
-    items: HashMap<SlotIndex, Option<u32>>,
+    items: HashMap<SlotIndex, Option<NetId>>,
     /// The number of rows of Loose Inventory available.
     rows: usize,
     /// The number of columns of Loose Inventory available.
@@ -115,20 +118,20 @@ impl Inventory {
 
     #[inline]
     /// Returns an iterator over the Loose Inventory
-    pub fn loose(&self) -> impl Iterator<Item = (&SlotIndex, &Option<u32>)> {
+    pub fn loose(&self) -> impl Iterator<Item = (&SlotIndex, &Option<NetId>)> {
         self.items.iter().filter(|(i, _)| i.is_loose())
     }
 
     #[inline]
     /// Returns an iterator over the Reserved Slots
-    pub fn reserved(&self) -> impl Iterator<Item = (&SlotIndex, &Option<u32>)> {
+    pub fn reserved(&self) -> impl Iterator<Item = (&SlotIndex, &Option<NetId>)> {
         self.items.iter().filter(|(i, _)| i.is_reserved())
     }
 
     #[inline]
     /// Returns a reference to the slot at the given SlotIndex
     /// if such a slot exists. Otherwise, an error is returned.
-    pub fn slot(&self, index: &SlotIndex) -> Result<&Option<u32>, Error> {
+    pub fn slot(&self, index: &SlotIndex) -> Result<&Option<NetId>, Error> {
         self.items
             .get(index)
             .map(|i| Ok(i))
@@ -139,7 +142,7 @@ impl Inventory {
     /// Clears the slot at the given SlotIndex by setting it to None,
     /// and returns the value of the slot previous to clearing it.
     /// Returns an error if no slot with that index cannot be found.
-    pub fn clear(&mut self, index: &SlotIndex) -> Result<Option<u32>, Error> {
+    pub fn clear(&mut self, index: &SlotIndex) -> Result<Option<NetId>, Error> {
         match self.items.insert(index.clone(), None) {
             // if this slot existed beforehand as it should have, return that
             Some(existing) => Ok(existing),
@@ -156,11 +159,11 @@ impl Inventory {
     }
 
     #[inline]
-    /// Finds an empty slot in the Loose Inventory, and inserts the provided item entity id into it.
+    /// Finds an empty slot in the Loose Inventory, and inserts the provided item's NetId into it.
     ///
     /// If an empty slot can be found, its index returned,
     /// but if no empty slot can be found an error is returned.
-    pub fn insert_loose(&mut self, ent: u32) -> Result<SlotIndex, Error> {
+    pub fn insert_loose(&mut self, ent: NetId) -> Result<SlotIndex, Error> {
         for col in 0..self.columns {
             for row in 0..self.rows {
                 let index = SlotIndex::Loose(row, col);
@@ -195,7 +198,7 @@ impl Inventory {
     ///
     /// If an empty slot can be found, a tuple representing its (row, column) is returned,
     /// but if no empty slot can be found an error is returned.
-    pub fn insert(&mut self, ent: u32, item: &Item) -> Result<SlotIndex, Error> {
+    pub fn insert(&mut self, ent: NetId, item: &Item) -> Result<SlotIndex, Error> {
         // first try to get that reserved spot for this item
         if Item::Misc != *item {
             let index = SlotIndex::Reserved(item.clone());
@@ -212,6 +215,16 @@ impl Inventory {
         // (which will return an error if it can't find anything)
         self.insert_loose(ent)
     }
+
+    #[inline]
+    /// Replaces the NetId in each slot with whatever `f` returns for it,
+    /// which is handy when the items an Inventory refers to have been given new NetIds.
+    /// Slots that `f` returns None for are left empty.
+    pub fn remap<F: FnMut(NetId) -> Option<NetId>>(&mut self, mut f: F) {
+        for slot in self.items.values_mut() {
+            *slot = slot.and_then(&mut f);
+        }
+    }
 }
 
 #[test]
@@ -220,13 +233,13 @@ fn inventory_integration() {
     inv.items.insert(SlotIndex::Reserved(Item::Weapon), None);
 
     // make sure special items get prioritized to the right slots
-    inv.insert(0, &Item::Weapon).unwrap();
-    assert!(inv.slot(&SlotIndex::Reserved(Item::Weapon)).unwrap() == &Some(0));
-    assert!(inv.reserved().next() == Some((&SlotIndex::Reserved(Item::Weapon), &Some(0))));
-    assert!(inv.clear(&SlotIndex::Reserved(Item::Weapon)).unwrap() == Some(0));
+    inv.insert(NetId(0), &Item::Weapon).unwrap();
+    assert!(inv.slot(&SlotIndex::Reserved(Item::Weapon)).unwrap() == &Some(NetId(0)));
+    assert!(inv.reserved().next() == Some((&SlotIndex::Reserved(Item::Weapon), &Some(NetId(0)))));
+    assert!(inv.clear(&SlotIndex::Reserved(Item::Weapon)).unwrap() == Some(NetId(0)));
 
     // make sure loose slots work
-    while let Ok(_) = inv.insert(0, &Item::Misc) {}
+    while let Ok(_) = inv.insert(NetId(0), &Item::Misc) {}
 
     let loose = inv.loose().map(|(i, e)| (i.clone(), e.clone())).collect::<Vec<_>>();
 
@@ -242,7 +255,7 @@ fn inventory_integration() {
         Loose(1, 3),
     ]
     .into_iter()
-    .map(|i| (i, Some(0_u32)))
+    .map(|i| (i, Some(NetId(0))))
     .for_each(|s| {
         assert!(
             loose.contains(&s),
@@ -250,7 +263,7 @@ fn inventory_integration() {
             s,
         );
         // clear it, which we can use as a marker to see if we've visited each slot
-        assert!(Some(0) == inv.clear(&s.0).unwrap());
+        assert!(Some(NetId(0)) == inv.clear(&s.0).unwrap());
     });
 
     // make sure that each slot is empty (that we cleared it)
@@ -296,12 +309,6 @@ impl SlotIndex {
     }
 }
 
-#[derive(Clone, Debug, Default, Component, Serialize, Deserialize)]
-/// This Component removes the Pos Component from an entity, preventing it
-/// from interacting physically from the world, making it able to be used as
-/// an item.
-pub struct Deposition;
-
 pub const MAX_INTERACTION_DISTANCE_SQUARED: f32 = {
     let f = 2.0;
     f * f
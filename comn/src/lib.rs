@@ -21,6 +21,7 @@ pub type Iso2 = na::Isometry2<f32>;
 pub use collide::shape::Cuboid;
 
 #[derive(Clone, Debug, Component, Serialize, Deserialize)]
+#[storage(FlaggedStorage)]
 pub struct Pos(pub Iso2);
 
 impl Pos {
@@ -30,11 +31,28 @@ impl Pos {
 }
 
 #[derive(Clone, Debug, Component, Serialize, Deserialize)]
+#[storage(FlaggedStorage)]
 pub struct Hitbox(pub Cuboid<f32>);
 
 #[derive(Default)]
 pub struct Fps(pub f32);
 
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+/// How many times the Server's dispatcher has run.
+/// Every Snapshot the Server sends out is labeled with one of these.
+pub struct Tick(pub u64);
+impl Tick {
+    /// How many times the Server's dispatcher runs each second.
+    /// The Server tells the Clients this when they join.
+    pub const PER_SECOND: f32 = 20.0;
+
+    /// How long the Server had been running when this tick happened,
+    /// given how many ticks it runs each second.
+    pub fn seconds(self, tick_rate: f32) -> f64 {
+        self.0 as f64 / tick_rate as f64
+    }
+}
+
 pub mod art;
 
 pub mod item;
@@ -47,46 +65,183 @@ pub mod controls;
 
 pub mod phys;
 
+pub mod ping;
+
 pub mod net {
-    pub use comp::NetComponent;
-    pub use msg::NetMessage;
+    pub use comp::{
+        register_net_components, visit_replicated, NetComponent, NetComponentKind, Replicated,
+        Replication, ReplicationVisitor,
+    };
+    pub use msg::{
+        ClientMessage, ResumeToken, ServerMessage, Snapshot, MAX_CHAT_LENGTH, PROTOCOL_VERSION,
+    };
     // UpdatePosition
     use super::prelude::*;
     use serde::{Deserialize, Serialize};
     use specs::{prelude::*, Component};
 
+    #[derive(
+        Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Component, Serialize, Deserialize,
+    )]
+    #[storage(FlaggedStorage)]
+    /// The Server gives one of these to each entity it tells the Clients about,
+    /// and entities are referred to by their NetId in all networking.
+    ///
+    /// specs reuses the ids of deleted entities, so sending those over the
+    /// wire would allow the Clients to mistake a new entity for an old one.
+    /// NetIds, on the other hand, are never given out twice.
+    pub struct NetId(pub u32);
+
     #[derive(Clone, Debug, Component, Serialize, Deserialize)]
     /// These wrap around an Iso2.
     /// They're sent from the Server to the Client
     /// to update positions, no entity on the Server
-    /// should have one of those, though they should
-    /// be fairly common on the Client.
+    /// should have one of those. The Client collects them up
+    /// to interpolate between, and the Tick of the Snapshot
+    /// they arrive in says when the entity was at this position.
     pub struct UpdatePosition {
         pub iso: Iso2,
-        // duration since UNIX_EPOCH
-        pub time_stamp: std::time::Duration,
+        /// Only the UpdatePositions for a Client's own player have this;
+        /// it's the seq of the last `controls::Input` the Server applied to them.
+        pub ack: Option<u32>,
     }
 
     #[derive(Clone, Debug, Component, Serialize, Deserialize)]
-    /// This is sent in by the player when they're ready
+    /// The Server puts this on a player's entity when they send in
+    /// a ClientMessage::SpawnPlayer, which they do when they're ready
     /// for their Pos and Appearance components.
     /// Essentially, when they want to enter the game world.
     /// Menu/Spectator -> Game
     pub struct SpawnPlayer;
 
     #[derive(Clone, Debug, Component, Serialize, Deserialize)]
-    /// The server attaches this to an entity on the clients to
-    /// tell clients which entity they are able to control.
+    #[storage(FlaggedStorage)]
+    /// The server attaches this to a player's entity, and that player's
+    /// client is told about it so that it knows which entity it's able to control.
     pub struct LocalPlayer;
 
     mod msg {
-        use super::NetComponent;
+        use super::{NetComponent, NetComponentKind, NetId};
+        use crate::art::{tilemap::Cell, Appearance, TileMap};
+        use crate::controls::Input;
+        use crate::item::{DropRequest, PickupRequest};
+        use crate::Tick;
         use serde::{Deserialize, Serialize};
 
+        /// This should be bumped whenever a change is made to any of the messages
+        /// the Client and Server send each other, or to anything inside of them,
+        /// so that Clients that are out of date can be turned away.
+        pub const PROTOCOL_VERSION: u32 = 8;
+
+        /// The most characters a ClientMessage::Chat can have; longer ones are turned away.
+        pub const MAX_CHAT_LENGTH: usize = 200;
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+        /// The Server hands one of these to each Client in its Welcome.
+        /// A Client that loses its connection can say Hello with it
+        /// to get its player back, so long as it isn't gone for too long.
+        pub struct ResumeToken(pub [u8; 16]);
+
+        #[derive(Deserialize, Serialize, Debug)]
+        /// The Server sends these to the Clients to keep
+        /// their worlds in line with its own.
+        pub enum ServerMessage {
+            /// The reply to a ClientMessage::Hello that the Server is happy with.
+            /// This is always the first thing a Client hears.
+            Welcome {
+                your_net_id: NetId,
+                tick_rate: f32,
+                server_name: String,
+                /// What the Server generated its world from,
+                /// so that it can be made again when something goes wrong in it.
+                /// None if it wasn't generated, like when it was loaded from a map.
+                world_seed: Option<u64>,
+                /// Say Hello with this to pick up where we left off after a disconnect.
+                /// A new one is given out with every Welcome.
+                resume_token: ResumeToken,
+                /// Whether this Client has been given back the player it had before;
+                /// if so, it's already logged in, and everything it knew about has
+                /// to be forgotten, since it's all about to be sent again.
+                resumed: bool,
+            },
+            /// The reply to a ClientMessage::Hello that the Server isn't happy with,
+            /// explaining why. The Server hangs up right after sending this.
+            Rejected(String),
+            /// The reply to a ClientMessage::Login that worked,
+            /// with the name of the account that's now logged in.
+            LoggedIn(String),
+            /// The reply to a ClientMessage::Login that didn't work, explaining why.
+            /// Unlike with Rejected, the Client can just try again.
+            LoginFailed(String),
+            NewEnt(NetId),
+            InsertComp(NetId, NetComponent),
+            /// The entity with this id is gone, and should be
+            /// deleted along with all of its components.
+            RemoveEnt(NetId),
+            /// The entity with this id no longer has this sort of component.
+            RemoveComp(NetId, NetComponentKind),
+            /// The whole floor; this comes right after the Welcome.
+            TileMap(TileMap),
+            /// The tile in this cell of the TileMap has changed.
+            SetTile(Cell, Option<Appearance>),
+            /// Answer this with a ClientMessage::Pong with the same id, right away;
+            /// see `comn::ping`.
+            Ping(u32),
+            /// The answer to the ClientMessage::Ping with this id.
+            Pong(u32),
+            /// Something somebody said, and the name of the account they said it from.
+            /// The Server's own messages, like its answers to commands, aren't from anyone.
+            Chat {
+                from: Option<String>,
+                text: String,
+            },
+        }
+
+        #[derive(Deserialize, Serialize, Debug)]
+        /// ServerMessages aren't sent on their own; instead, everything
+        /// the Server has to say to a Client during a tick is sent along
+        /// in one of these, so that the Client can apply it all at once.
+        pub struct Snapshot {
+            pub tick: Tick,
+            pub messages: Vec<ServerMessage>,
+        }
+
         #[derive(Deserialize, Serialize, Debug)]
-        pub enum NetMessage {
-            NewEnt(u32),
-            InsertComp(u32, NetComponent),
+        /// The Clients send these to the Server.
+        /// None of these carry authoritative state; they only describe
+        /// what the player would like to do, and it's up to the Server
+        /// to decide if (and how) that actually happens.
+        pub enum ClientMessage {
+            /// This has to be the first thing a Client sends;
+            /// anything sent before it is ignored.
+            Hello {
+                protocol_version: u32,
+                /// Just for the logs; this can be whatever.
+                client_build: String,
+                /// The ResumeToken from the last Welcome, when reconnecting.
+                resume_token: Option<ResumeToken>,
+            },
+            /// Logs into an account, or makes a new one if nobody has this username yet.
+            /// This has to be done before a player can be spawned, since it's the
+            /// account that says where they were and what they had the last time they played.
+            Login {
+                username: String,
+                password: String,
+            },
+            /// See `net::SpawnPlayer`.
+            SpawnPlayer,
+            /// See `controls::Input`.
+            Input(Input),
+            PickupRequest(PickupRequest),
+            DropRequest(DropRequest),
+            /// Answer this with a ServerMessage::Pong with the same id, right away;
+            /// see `comn::ping`.
+            Ping(u32),
+            /// The answer to the ServerMessage::Ping with this id.
+            Pong(u32),
+            /// Something typed into the chat box. Anything starting with a slash
+            /// is a command for the Server, like "/who", rather than something to say.
+            Chat(String),
         }
     }
 
@@ -94,11 +249,46 @@ pub mod net {
         // util includes
         use crate::Pos;
         use serde::{Deserialize, Serialize};
-        use specs::{Entity, LazyUpdate};
+        use specs::{Component, Entity, LazyUpdate, Tracked, World, WorldExt};
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        /// Which Clients the Server tells about a sort of component,
+        /// whenever one is inserted, modified, or removed.
+        pub enum Replication {
+            /// Nobody; these are either sent by hand, or not at all.
+            Never,
+            /// Only the Client whose player entity has the component.
+            Owner,
+            /// Every Client.
+            Everyone,
+        }
+
+        /// net_component! implements this for every NetComponent,
+        /// so that the Server can replicate each one according to
+        /// the Replication listed next to it.
+        pub trait Replicated: Component + Clone + Into<NetComponent> + Send + Sync {
+            const KIND: NetComponentKind;
+            const REPLICATION: Replication;
+        }
+
+        /// See `visit_replicated`.
+        pub trait ReplicationVisitor {
+            fn visit<C>(&mut self)
+            where
+                C: Replicated,
+                C::Storage: Tracked;
+        }
+
+        macro_rules! visit_if_replicated {
+            ( $visitor:ident, Never, $y:ty ) => {};
+            ( $visitor:ident, $policy:ident, $y:ty ) => {
+                $visitor.visit::<$y>();
+            };
+        }
 
         macro_rules! net_component_base {
-            ( $( $x:tt : $y:ty $(: $extra:ident)? ),+ $(,)? ) => {
-                #[derive(Deserialize, Serialize, Debug)]
+            ( $( $x:tt : $y:ty => $policy:ident ),+ $(,)? ) => {
+                #[derive(Deserialize, Serialize, Debug, Clone)]
                 pub enum NetComponent {
                     $(
                         $x($y),
@@ -113,6 +303,16 @@ pub mod net {
                     }
                 )+
 
+                #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+                /// Each variant of this refers to the variant of NetComponent with
+                /// the same name, so that a sort of component can be talked about
+                /// without having to send one along.
+                pub enum NetComponentKind {
+                    $(
+                        $x,
+                    )+
+                }
+
                 impl NetComponent {
                     pub fn insert(self, ent: Entity, lu: &LazyUpdate) {
                         match self {
@@ -121,52 +321,94 @@ pub mod net {
                             )+
                         }
                     }
+
+                    pub fn kind(&self) -> NetComponentKind {
+                        match self {
+                            $(
+                                NetComponent::$x(_) => NetComponentKind::$x,
+                            )+
+                        }
+                    }
+                }
+
+                impl NetComponentKind {
+                    pub fn remove(self, ent: Entity, lu: &LazyUpdate) {
+                        match self {
+                            $(
+                                NetComponentKind::$x => lu.remove::<$y>(ent),
+                            )+
+                        }
+                    }
+                }
+
+                $(
+                    impl Replicated for $y {
+                        const KIND: NetComponentKind = NetComponentKind::$x;
+                        const REPLICATION: Replication = Replication::$policy;
+                    }
+                )+
+
+                /// Calls `visitor.visit` once for every sort of NetComponent
+                /// that isn't listed as `Never` being replicated.
+                /// Those all need to be stored in a FlaggedStorage, so that
+                /// the Server can find out when they change.
+                pub fn visit_replicated<V: ReplicationVisitor>(visitor: &mut V) {
+                    $(
+                        visit_if_replicated!(visitor, $policy, $y);
+                    )+
+                }
+
+                /// Registers a storage for every sort of NetComponent, for worlds
+                /// that are built up entirely out of what the Server sends them.
+                pub fn register_net_components(world: &mut World) {
+                    $(
+                        world.register::<$y>();
+                    )+
                 }
             };
         }
 
         macro_rules! net_component {
-            ( $( $name:ident $(: $inner:ty)? ),+ $(,)? ) => {
+            ( $( $name:ident : $policy:ident ),+ $(,)? ) => {
                 net_component_base! {
-                    $($name $(: $inner)? : $name),*
+                    $($name : $name => $policy),*
                 }
             }
         }
 
         // Component includes
-        use super::{LocalPlayer, SpawnPlayer, UpdatePosition};
-        use crate::art::{Animate, Appearance, PlayerAnimationController, Tile};
+        use super::{LocalPlayer, UpdatePosition};
+        use crate::art::{Animate, Appearance, PlayerAnimationController};
         use crate::controls::{Camera, Heading};
         use crate::dead::Dead;
-        use crate::item::{Deposition, DropRequest, Inventory, PickupRequest};
+        use crate::item::Inventory;
         use crate::{Hitbox, Item};
 
         net_component! {
             // art
-            Appearance,
-            Tile,
-            Animate,
-            PlayerAnimationController,
+            Appearance: Everyone,
+            Animate: Everyone,
+            PlayerAnimationController: Everyone,
 
             // inventory
-            Item,
-            Deposition,
-            Inventory,
-            PickupRequest,
-            DropRequest,
+            Item: Everyone,
+            Inventory: Owner,
 
             // phys/net
-            Pos,
-            Hitbox,
-            UpdatePosition,
-            SpawnPlayer,
-            LocalPlayer,
-            Heading,
-            Camera,
+            // (modifications to Pos are streamed to clients as UpdatePositions)
+            Pos: Everyone,
+            Hitbox: Everyone,
+            UpdatePosition: Never,
+            LocalPlayer: Owner,
+            Heading: Never,
+            Camera: Never,
 
             // util
-            Dead,
+            // (RemoveEnt is sent instead)
+            Dead: Never,
         }
     }
 }
-pub use net::{NetComponent, NetMessage};
+pub use net::{
+    ClientMessage, NetComponent, NetComponentKind, NetId, ServerMessage, Snapshot, PROTOCOL_VERSION,
+};
@@ -0,0 +1,94 @@
+//! Every so often, each side of a connection sends the other a Ping,
+//! which is answered with a Pong as soon as it arrives.
+//! Timing how long that takes tells the Server how laggy each Client is,
+//! and tells the Clients how far behind the Server they're hearing about things.
+//!
+//! The two sides don't share a clock, so everything here is timed
+//! in seconds on whichever side sent the Ping.
+use specs::{prelude::*, Component};
+use std::collections::VecDeque;
+
+/// How many seconds apart Pings are sent.
+pub const PING_INTERVAL: f64 = 1.0;
+
+/// Pings that haven't been answered after this many seconds are given up on.
+const PING_TIMEOUT: f64 = 10.0;
+
+/// How much each new round trip moves the average; lower is smoother.
+const SMOOTHING: f64 = 0.125;
+
+#[derive(Component, Clone, Debug, Default)]
+/// How long it takes a message to get to the other side and back, as best we can tell.
+pub struct Latency {
+    /// A running average of the round trips measured so far.
+    rtt: Option<f64>,
+    /// The Pings that haven't been answered yet, and when they were sent, oldest first.
+    pending: VecDeque<(u32, f64)>,
+    next_id: u32,
+    last_sent: Option<f64>,
+}
+impl Latency {
+    /// The average round trip time, in seconds, once there's been one.
+    pub fn rtt(&self) -> Option<f64> {
+        self.rtt
+    }
+
+    /// Returns the id of a Ping to send, if it's been PING_INTERVAL since the last one.
+    pub fn ping(&mut self, now: f64) -> Option<u32> {
+        if self
+            .last_sent
+            .map_or(false, |last| now - last < PING_INTERVAL)
+        {
+            return None;
+        }
+
+        while self
+            .pending
+            .front()
+            .map_or(false, |(_, sent)| now - sent > PING_TIMEOUT)
+        {
+            self.pending.pop_front();
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.push_back((id, now));
+        self.last_sent = Some(now);
+        Some(id)
+    }
+
+    /// Call this when a Pong comes back. If it's for one of our Pings,
+    /// returns how long that round trip took, in seconds.
+    pub fn pong(&mut self, id: u32, now: f64) -> Option<f64> {
+        let index = self
+            .pending
+            .iter()
+            .position(|(pending, _)| *pending == id)?;
+        // they're answered in order, so anything older isn't coming back.
+        let (_, sent) = self.pending.drain(..=index).last()?;
+
+        let sample = now - sent;
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt + (sample - rtt) * SMOOTHING,
+            None => sample,
+        });
+        Some(sample)
+    }
+}
+
+#[test]
+fn round_trips_are_averaged() {
+    let mut latency = Latency::default();
+    let first = latency.ping(0.0).unwrap();
+    assert_eq!(latency.ping(0.5), None);
+    assert_eq!(latency.pong(first, 0.2), Some(0.2));
+    assert_eq!(latency.rtt(), Some(0.2));
+
+    let second = latency.ping(1.0).unwrap();
+    assert_eq!(latency.pong(second, 1.0 + 0.2 + 0.8), Some(1.0));
+    assert_eq!(latency.rtt(), Some(0.2 + 0.8 * SMOOTHING));
+
+    // nobody asked for this one, or it's already been answered.
+    assert_eq!(latency.pong(second, 2.5), None);
+    assert_eq!(latency.pong(1234, 2.5), None);
+}
@@ -0,0 +1,268 @@
+//! Players log into accounts, so that they get the same character back
+//! whenever they come back, wherever they're connecting from.
+//!
+//! Accounts are kept in their own file, apart from the world's save, since they
+//! change whenever someone logs in or out. Each account's character is kept with it,
+//! along with everything in their Inventory, which only exists in the world
+//! while they're playing.
+use crate::net::NetIds;
+use crate::save::{self, SaveError, SavedEntity, SAVE_VERSION};
+use comn::item::Inventory;
+use comn::prelude::*;
+use comn::specs::{prelude::*, world::EntitiesRes, WorldExt};
+use comn::Dead;
+// util
+use log::*;
+use serde::{Deserialize, Serialize};
+use specs_derive::Component;
+use std::collections::{HashMap, HashSet};
+use std::{fmt, fs, io, path::Path, path::PathBuf};
+
+/// Usernames can't be any longer than this.
+pub const MAX_USERNAME_LENGTH: usize = 24;
+
+#[derive(Component, Clone, Debug)]
+#[storage(DenseVecStorage)]
+/// Clients get this once they've logged in, with the name of their account.
+pub struct LoggedIn(pub String);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Where a player was and what they had when they last logged out.
+pub struct Character {
+    pos: Iso2,
+    inventory: Inventory,
+    /// Everything in their Inventory.
+    items: Vec<SavedEntity>,
+}
+impl Character {
+    /// Writes down this player's character, if they've been spawned in.
+    pub fn take(world: &World, player: Entity) -> Option<Self> {
+        let Pos(pos) = world.read_storage::<Pos>().get(player)?.clone();
+        let inventory = world.read_storage::<Inventory>().get(player)?.clone();
+
+        let items = save::take_entities(world, &held(world, &inventory));
+        Some(Self {
+            pos,
+            inventory,
+            items,
+        })
+    }
+
+    /// Puts this character's items back in the world, and their Pos and Inventory
+    /// on this player. Everything's inserted lazily.
+    pub fn restore(self, ents: &EntitiesRes, lu: &LazyUpdate, ids: &mut NetIds, player: Entity) {
+        let Character {
+            pos,
+            mut inventory,
+            items,
+        } = self;
+
+        let remapped = save::restore_entities(ents, lu, ids, items);
+        inventory.remap(|old| remapped.get(&old).cloned());
+
+        lu.insert(player, Pos(pos));
+        lu.insert(player, inventory);
+    }
+}
+
+/// The entities of everything in this Inventory.
+fn held(world: &World, inventory: &Inventory) -> HashSet<Entity> {
+    let ids = world.read_resource::<NetIds>();
+    inventory
+        .loose()
+        .chain(inventory.reserved())
+        .filter_map(|(_, id)| id.and_then(|id| ids.entity(id)))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    /// Passwords are never stored; only an argon2 hash of them, with the salt.
+    password: String,
+    /// None until they've played and logged out at least once.
+    character: Option<Character>,
+}
+
+#[derive(Debug)]
+/// Why someone couldn't log in. These are shown to the player.
+pub enum LoginError {
+    BadUsername,
+    EmptyPassword,
+    WrongPassword,
+    /// Someone else is already playing on this account.
+    AlreadyPlaying,
+}
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoginError::BadUsername => write!(
+                f,
+                "Usernames have to be 1 to {} letters, numbers, dashes or underscores.",
+                MAX_USERNAME_LENGTH
+            ),
+            LoginError::EmptyPassword => write!(f, "Passwords can't be empty."),
+            LoginError::WrongPassword => write!(f, "That's not the right password."),
+            LoginError::AlreadyPlaying => write!(f, "Someone's already playing on that account."),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// What's written to the accounts file.
+struct AccountsFile {
+    /// Characters are saved like the world is, so they share its version.
+    version: u32,
+    accounts: HashMap<String, Account>,
+}
+
+#[derive(Default)]
+/// Every account there is, by username.
+pub struct Accounts {
+    accounts: HashMap<String, Account>,
+    /// Where these are written to; if this is None, they're forgotten on shutdown.
+    path: Option<PathBuf>,
+}
+impl Accounts {
+    /// Reads the accounts kept at `path`, and keeps them there from now on.
+    /// There not being anything there yet is fine; it's made on the first write.
+    pub fn load(path: &Path) -> Result<Self, SaveError> {
+        let accounts = if path.exists() {
+            let text = fs::read_to_string(path).map_err(SaveError::Io)?;
+            let file: AccountsFile = ron::de::from_str(&text).map_err(SaveError::Parse)?;
+            if file.version != SAVE_VERSION {
+                return Err(SaveError::Version {
+                    found: file.version,
+                });
+            }
+            file.accounts
+        } else {
+            HashMap::new()
+        };
+        info!("{} accounts on file", accounts.len());
+
+        Ok(Self {
+            accounts,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Writes every account to the file they were loaded from, if there was one.
+    pub fn write(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let file = AccountsFile {
+            version: SAVE_VERSION,
+            accounts: self.accounts.clone(),
+        };
+        save::write_ron(path, &file)
+    }
+
+    /// Checks this password against the account with this username,
+    /// making the account with this password if there isn't one yet.
+    pub fn log_in(&mut self, username: &str, password: &str) -> Result<(), LoginError> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if username.is_empty()
+            || username.len() > MAX_USERNAME_LENGTH
+            || !username.chars().all(valid)
+        {
+            return Err(LoginError::BadUsername);
+        }
+        if password.is_empty() {
+            return Err(LoginError::EmptyPassword);
+        }
+
+        match self.accounts.get(username) {
+            Some(account) => {
+                let matches = argon2::verify_encoded(&account.password, password.as_bytes())
+                    .unwrap_or_else(|e| {
+                        error!("{}'s password hash couldn't be checked: {}", username, e);
+                        false
+                    });
+                if !matches {
+                    return Err(LoginError::WrongPassword);
+                }
+            }
+            None => {
+                info!("making a new account for {}", username);
+                let salt: [u8; 16] = rand::random();
+                let password =
+                    argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())
+                        .expect("Couldn't hash password");
+                self.accounts.insert(
+                    username.to_string(),
+                    Account {
+                        password,
+                        character: None,
+                    },
+                );
+                self.write_or_log();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The character this account had when it last logged out, if it's ever played.
+    pub fn character(&self, username: &str) -> Option<&Character> {
+        self.accounts.get(username)?.character.as_ref()
+    }
+
+    pub fn store(&mut self, username: &str, character: Character) {
+        match self.accounts.get_mut(username) {
+            Some(account) => account.character = Some(character),
+            None => error!("Can't store character for unknown account {}", username),
+        }
+    }
+
+    /// Losing accounts isn't worth stopping the game over, so that's only logged.
+    pub fn write_or_log(&self) {
+        if let Err(e) = self.write() {
+            error!("Couldn't write accounts: {}", e);
+        }
+    }
+}
+
+/// Writes down the characters of everyone who's playing right now,
+/// so they aren't lost if the Server goes down before they log out.
+pub fn store_everyone(world: &World) {
+    let characters = (&world.entities(), &world.read_storage::<LoggedIn>())
+        .join()
+        .filter_map(|(player, LoggedIn(username))| {
+            Some((username.clone(), Character::take(world, player)?))
+        })
+        .collect::<Vec<_>>();
+
+    let mut accounts = world.write_resource::<Accounts>();
+    for (username, character) in characters {
+        accounts.store(&username, character);
+    }
+    accounts.write_or_log();
+}
+
+/// Writes down this player's character, then clears away everything in their
+/// Inventory, since it's kept with their account until they log in again.
+/// The player's entity itself should be cleared away separately.
+pub fn log_out(world: &World, player: Entity) {
+    let username = match world.read_storage::<LoggedIn>().get(player) {
+        Some(LoggedIn(username)) => username.clone(),
+        None => return,
+    };
+
+    // they might not have been spawned in yet.
+    if let Some(character) = Character::take(world, player) {
+        let items = held(world, &character.inventory);
+        let mut dead = world.write_storage::<Dead>();
+        for item in items {
+            dead.insert(item, Dead)
+                .expect("Couldn't clear away logged out player's items");
+        }
+
+        let mut accounts = world.write_resource::<Accounts>();
+        accounts.store(&username, character);
+        accounts.write_or_log();
+    }
+    info!("{} logged out", username);
+}
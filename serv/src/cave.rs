@@ -0,0 +1,232 @@
+//! Caves are grown with a cellular automaton: the grid starts out as noise,
+//! then each cell becomes whatever most of its neighbors are, a few times over,
+//! which leaves behind smooth caverns. Any caverns that aren't connected to
+//! the spawn area are then tunnelled to, so that every bit of floor is reachable.
+use comn::art::TileMap;
+use comn::prelude::*;
+// util
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+pub use comn::art::{tilemap::Cell, TILE_SIZE};
+
+/// Caverns with fewer floor cells than this aren't worth tunnelling to,
+/// so they're filled back in instead.
+const MIN_CAVERN: usize = 6;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// Everything about how a cave is generated, except for how big it is.
+pub struct CaveConfig {
+    /// The chance each cell starts out as wall, before the caverns are grown.
+    /// Anywhere from 0.4 to 0.5 gives decent caves; more than that gives
+    /// thin tunnels, and less gives one big open room.
+    pub density: f32,
+    /// How many times the cellular automaton is run.
+    pub smoothing: u32,
+    /// How many cells out from the spawn point are always kept clear.
+    pub spawn_radius: u32,
+    /// The chance each floor cell along a wall has a stalagmite on it.
+    pub stalagmites: f32,
+    /// The chance each floor cell has a key on it.
+    pub keys: f32,
+}
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            density: 0.45,
+            smoothing: 4,
+            spawn_radius: 2,
+            stalagmites: 0.15,
+            keys: 0.02,
+        }
+    }
+}
+
+/// Which cells of a square grid are floor, and which are wall.
+/// The cells along the edge of the grid are always wall.
+pub struct Cave {
+    pub size: usize,
+    floor: Vec<bool>,
+    /// Nothing is put on the cells this close to here.
+    pub spawn: Cell,
+}
+impl Cave {
+    /// Grows a cave with `size` cells on each side, where every floor cell
+    /// can be walked to from the one closest to `spawn_point`.
+    pub fn generate<R: Rng>(
+        size: usize,
+        spawn_point: Vec2,
+        config: &CaveConfig,
+        rng: &mut R,
+    ) -> Self {
+        assert!(
+            size >= 3,
+            "a cave has to be at least 3 cells wide, not {}",
+            size
+        );
+
+        let mut cave = Self {
+            size,
+            floor: vec![false; size * size],
+            spawn: Self::cell_at(size, spawn_point),
+        };
+
+        for cell in cave.inner_cells() {
+            let wall = rng.gen::<f32>() < config.density;
+            cave.set(cell, !wall);
+        }
+
+        for _ in 0..config.smoothing {
+            cave.smooth();
+        }
+
+        for cell in cave.inner_cells() {
+            if cave.near_spawn(cell, config.spawn_radius) {
+                cave.set(cell, true);
+            }
+        }
+
+        cave.connect();
+        cave
+    }
+
+    /// The cell closest to this point, kept off of the edge of the grid.
+    fn cell_at(size: usize, point: Vec2) -> Cell {
+        let clamp = |v: f32| ((v / TILE_SIZE).round().max(1.0) as usize).min(size - 2);
+        (clamp(point.x), clamp(point.y))
+    }
+
+    /// Where the center of this cell is, in the world;
+    /// caves line up with the TileMap they're drawn on.
+    pub fn loc(cell: Cell) -> Vec2 {
+        TileMap::loc(cell)
+    }
+
+    /// The middle of a cave this big, in the world.
+    pub fn center(size: usize) -> Vec2 {
+        Self::loc((size / 2, size / 2))
+    }
+
+    pub fn is_floor(&self, (x, y): Cell) -> bool {
+        self.floor[y * self.size + x]
+    }
+
+    fn set(&mut self, (x, y): Cell, floor: bool) {
+        self.floor[y * self.size + x] = floor;
+    }
+
+    /// Every cell that isn't on the edge of the grid.
+    fn inner_cells(&self) -> impl Iterator<Item = Cell> {
+        let size = self.size;
+        (1..size - 1).flat_map(move |y| (1..size - 1).map(move |x| (x, y)))
+    }
+
+    /// The cells around this one, diagonals included.
+    pub fn neighbors(&self, (x, y): Cell) -> impl Iterator<Item = Cell> {
+        let size = self.size as isize;
+        let (x, y) = (x as isize, y as isize);
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+            .filter(move |&(nx, ny)| {
+                (nx, ny) != (x, y) && nx >= 0 && ny >= 0 && nx < size && ny < size
+            })
+            .map(|(nx, ny)| (nx as usize, ny as usize))
+    }
+
+    /// Each cell becomes wall if most of its neighbors are, and floor if few are.
+    fn smooth(&mut self) {
+        let walls = self
+            .inner_cells()
+            .map(|cell| {
+                let walls = self.neighbors(cell).filter(|&n| !self.is_floor(n)).count();
+                (cell, walls)
+            })
+            .collect::<Vec<_>>();
+
+        for (cell, walls) in walls {
+            if walls > 4 {
+                self.set(cell, false);
+            } else if walls < 4 {
+                self.set(cell, true);
+            }
+        }
+    }
+
+    /// Every floor cell connected to `start` without going diagonally.
+    fn cavern(&self, start: Cell, seen: &mut [bool]) -> Vec<Cell> {
+        let mut cavern = Vec::new();
+        let mut frontier = VecDeque::new();
+        frontier.push_back(start);
+        seen[start.1 * self.size + start.0] = true;
+
+        while let Some((x, y)) = frontier.pop_front() {
+            cavern.push((x, y));
+            for next in &[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                let i = next.1 * self.size + next.0;
+                if self.is_floor(*next) && !seen[i] {
+                    seen[i] = true;
+                    frontier.push_back(*next);
+                }
+            }
+        }
+
+        cavern
+    }
+
+    /// Tunnels from each cavern to the spawn cell, filling in the tiny ones.
+    fn connect(&mut self) {
+        let mut seen = vec![false; self.size * self.size];
+        self.cavern(self.spawn, &mut seen);
+
+        let mut caverns = Vec::new();
+        for cell in self.inner_cells() {
+            if self.is_floor(cell) && !seen[cell.1 * self.size + cell.0] {
+                caverns.push(self.cavern(cell, &mut seen));
+            }
+        }
+
+        for cavern in caverns {
+            if cavern.len() < MIN_CAVERN {
+                for cell in cavern {
+                    self.set(cell, false);
+                }
+                continue;
+            }
+
+            // Both ends are inside of the edge of the grid,
+            // so a tunnel between them never touches it either.
+            let (mut x, mut y) = cavern[0];
+            let (sx, sy) = self.spawn;
+            while x != sx {
+                x = if x < sx { x + 1 } else { x - 1 };
+                self.set((x, y), true);
+            }
+            while y != sy {
+                y = if y < sy { y + 1 } else { y - 1 };
+                self.set((x, y), true);
+            }
+        }
+    }
+
+    /// Every floor cell.
+    pub fn floors<'a>(&'a self) -> impl Iterator<Item = Cell> + 'a {
+        self.inner_cells().filter(move |&c| self.is_floor(c))
+    }
+
+    /// Every wall cell next to a floor cell; these are all anyone will ever see of the walls.
+    pub fn edges<'a>(&'a self) -> impl Iterator<Item = Cell> + 'a {
+        let size = self.size;
+        (0..size)
+            .flat_map(move |y| (0..size).map(move |x| (x, y)))
+            .filter(move |&c| !self.is_floor(c) && self.neighbors(c).any(|n| self.is_floor(n)))
+    }
+
+    /// Whether this cell is close enough to spawn that nothing should be put on it.
+    pub fn near_spawn(&self, (x, y): Cell, radius: u32) -> bool {
+        let (sx, sy) = self.spawn;
+        let r = radius as usize;
+        x + r >= sx && x <= sx + r && y + r >= sy && y <= sy + r
+    }
+}
@@ -0,0 +1,271 @@
+//! Players talk to each other by typing into the chat box, and to the Server
+//! by starting what they type with a slash, like "/who".
+//!
+//! HandleChat only knows how to say things and answer "/help";
+//! every other command is up to whichever system registered it.
+//! Those systems register their commands in `System::setup`,
+//! then each tick look through `Commands::issued` for theirs, like `Who` does.
+// our code
+use crate::accounts::LoggedIn;
+use crate::net::{Client, ConnectionManager};
+use comn::prelude::*;
+use comn::specs::prelude::*;
+use comn::{art::TILE_SIZE, net::MAX_CHAT_LENGTH, ServerMessage};
+// crates
+use log::*;
+use specs_derive::Component;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+/// How many messages someone can send one right after the other.
+pub const CHAT_BURST: f64 = 5.0;
+/// How many messages each second someone can keep on sending after that.
+pub const CHAT_RATE: f64 = 0.5;
+
+#[derive(Default)]
+/// Everything Clients typed into the chat box since the last tick, and who typed it.
+/// HandleClientPackets fills this, and HandleChat empties it.
+pub struct ChatInbox(pub Vec<(Entity, String)>);
+
+#[derive(Component, Clone, Debug)]
+#[storage(DenseVecStorage)]
+/// Keeps people from flooding the chat; each message uses up one of these,
+/// and they come back at CHAT_RATE, up to CHAT_BURST.
+pub struct ChatLimit {
+    allowance: f64,
+    last: f64,
+}
+impl ChatLimit {
+    fn new(now: f64) -> Self {
+        Self {
+            allowance: CHAT_BURST,
+            last: now,
+        }
+    }
+
+    /// Whether or not they can say something now; if they can, that's used up.
+    fn allow(&mut self, now: f64) -> bool {
+        self.allowance = (self.allowance + (now - self.last) * CHAT_RATE).min(CHAT_BURST);
+        self.last = now;
+        if self.allowance >= 1.0 {
+            self.allowance -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Someone typed "/name args...".
+pub struct Command {
+    pub from: Entity,
+    pub addr: SocketAddr,
+    pub name: String,
+    pub args: Vec<String>,
+}
+impl Command {
+    /// Answers whoever issued this command; only they see it.
+    pub fn reply(&self, cm: &ConnectionManager, text: impl Into<String>) {
+        cm.send(
+            self.addr,
+            ServerMessage::Chat {
+                from: None,
+                text: text.into(),
+            },
+        );
+    }
+}
+
+#[derive(Default)]
+/// The commands players can issue, and those they issued this tick.
+pub struct Commands {
+    /// Each command's name, and what it does, for "/help".
+    known: BTreeMap<String, String>,
+    issued: Vec<Command>,
+}
+impl Commands {
+    /// Lets players issue "/name"; anything else gets turned away.
+    /// Registering the same name twice is a mistake, so that panics.
+    pub fn register(&mut self, name: &str, help: &str) {
+        if self
+            .known
+            .insert(name.to_string(), help.to_string())
+            .is_some()
+        {
+            panic!("two systems registered the /{} command", name);
+        }
+    }
+
+    /// The "/name" commands issued this tick, oldest first.
+    pub fn issued<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Command> + 'a {
+        self.issued.iter().filter(move |cmd| cmd.name == name)
+    }
+
+    /// Splits "/name some args" into its name and its args,
+    /// or hands back None if it isn't a command at all.
+    fn parse(text: &str) -> Option<(String, Vec<String>)> {
+        if !text.starts_with('/') {
+            return None;
+        }
+        let mut words = text[1..].split_whitespace();
+        let name = words.next()?.to_lowercase();
+        Some((name, words.map(|w| w.to_string()).collect()))
+    }
+}
+
+/// This system goes through everything in the ChatInbox, turning away
+/// what's too long or too soon, then passes what's said on to whoever
+/// can hear it and records the commands for the systems that handle them.
+pub struct HandleChat;
+impl<'a> System<'a> for HandleChat {
+    type SystemData = (
+        Read<'a, ConnectionManager>,
+        Write<'a, ChatInbox>,
+        Write<'a, Commands>,
+        Read<'a, crate::ServerConfig>,
+        Entities<'a>,
+        ReadStorage<'a, Client>,
+        ReadStorage<'a, LoggedIn>,
+        ReadStorage<'a, Pos>,
+        WriteStorage<'a, ChatLimit>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        <Self::SystemData as SystemData>::setup(world);
+        world
+            .fetch_mut::<Commands>()
+            .register("help", "lists every command");
+    }
+
+    fn run(
+        &mut self,
+        (cm, mut inbox, mut commands, config, ents, clients, logged_in, poses, mut limits): Self::SystemData,
+    ) {
+        commands.issued.clear();
+        let now = cm.uptime();
+
+        for (ent, text) in inbox.0.drain(..) {
+            let (Client(addr), LoggedIn(username)) = match (clients.get(ent), logged_in.get(ent)) {
+                (Some(client), Some(logged_in)) => (client, logged_in),
+                _ => {
+                    warn!("Client {} tried to chat without logging in", ent.id());
+                    continue;
+                }
+            };
+            let notice = |text: String| cm.send(*addr, ServerMessage::Chat { from: None, text });
+
+            // everything counts against the limit, even what's turned away,
+            // since turning it away still costs us a notice.
+            let allowed = limits
+                .entry(ent)
+                .expect("Couldn't get ChatLimit entry for Client")
+                .or_insert_with(|| ChatLimit::new(now))
+                .allow(now);
+            if !allowed {
+                debug!("{} is chatting too fast", username);
+                notice("Slow down!".to_string());
+                continue;
+            }
+
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            if text.chars().count() > MAX_CHAT_LENGTH {
+                debug!("{} tried to say something too long", username);
+                notice(format!(
+                    "That's too long to say; keep it under {} characters.",
+                    MAX_CHAT_LENGTH
+                ));
+                continue;
+            }
+
+            match Commands::parse(text) {
+                Some((name, _)) if name == "help" => {
+                    let help = commands
+                        .known
+                        .iter()
+                        .map(|(name, help)| format!("/{} - {}", name, help))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    notice(help);
+                }
+                Some((name, args)) => {
+                    debug!("{} issued /{} {:?}", username, name, args);
+                    if commands.known.contains_key(&name) {
+                        commands.issued.push(Command {
+                            from: ent,
+                            addr: *addr,
+                            name,
+                            args,
+                        });
+                    } else {
+                        notice(format!("There's no /{} command; try /help.", name));
+                    }
+                }
+                None => {
+                    info!("{}: {}", username, text);
+
+                    // with a chat_radius, only those nearby hear it,
+                    // though whoever said it always hears themselves.
+                    let radius = config.chat_radius.map(|tiles| tiles * TILE_SIZE);
+                    let here = poses.get(ent).map(|Pos(iso)| iso.translation.vector);
+                    for (other, Client(other_addr)) in (&*ents, &clients).join() {
+                        let hears = other == ent
+                            || match (radius, here) {
+                                (None, _) => true,
+                                (Some(radius), Some(here)) => {
+                                    poses.get(other).map_or(false, |Pos(iso)| {
+                                        (iso.translation.vector - here).magnitude() <= radius
+                                    })
+                                }
+                                (Some(_), None) => false,
+                            };
+                        if hears {
+                            cm.send(
+                                *other_addr,
+                                ServerMessage::Chat {
+                                    from: Some(username.clone()),
+                                    text: text.to_string(),
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// This system answers "/who" with everyone who's logged in.
+pub struct Who;
+impl<'a> System<'a> for Who {
+    type SystemData = (
+        Read<'a, ConnectionManager>,
+        Read<'a, Commands>,
+        ReadStorage<'a, Client>,
+        ReadStorage<'a, LoggedIn>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        <Self::SystemData as SystemData>::setup(world);
+        world
+            .fetch_mut::<Commands>()
+            .register("who", "lists everyone who's playing");
+    }
+
+    fn run(&mut self, (cm, commands, clients, logged_in): Self::SystemData) {
+        for cmd in commands.issued("who") {
+            let mut names = (&clients, &logged_in)
+                .join()
+                .map(|(_, LoggedIn(name))| name.as_str())
+                .collect::<Vec<_>>();
+            names.sort();
+            cmd.reply(
+                &cm,
+                format!("{} playing: {}", names.len(), names.join(", ")),
+            );
+        }
+    }
+}
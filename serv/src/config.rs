@@ -0,0 +1,240 @@
+//! Everything an operator might want to change about a Server without recompiling it.
+//! It's read from a TOML file, then anything passed on the command line overrides that.
+use crate::cave::{Cave, CaveConfig};
+use comn::prelude::*;
+// util
+use log::LevelFilter;
+use serde::Deserialize;
+use std::{fs, net::SocketAddr, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// Systems can read this as a resource to see how the Server was set up.
+pub struct ServerConfig {
+    /// Where to listen for clients.
+    pub bind: SocketAddr,
+    /// How many times each second the game loop runs.
+    pub tick_rate: f32,
+    /// How much to tell the operator about what's going on.
+    pub log_level: LevelFilter,
+    /// How many tiles wide and tall the world is; at least 3.
+    pub world_size: u32,
+    /// How far away from their player, in world units, things can be
+    /// before a client stops being told about them; see `net::InterestRadius`.
+    pub interest_radius: f32,
+    /// Where new players are put when they first spawn in.
+    /// If none is given, they're put in the middle of the world.
+    pub spawn_point: Option<[f32; 2]>,
+    /// The same seed always generates the same world.
+    /// If none is given, a random one is picked.
+    pub seed: u64,
+    /// How the caves in the world are grown.
+    pub cave: CaveConfig,
+    /// A map file to load the world from; see `map`.
+    /// If none is given, a cave is generated instead.
+    pub map: Option<PathBuf>,
+    /// Where the world is saved, so that it's still there after a restart.
+    /// If there's already a save here, it's loaded instead of the map
+    /// or a generated world. If none is given, nothing is saved.
+    pub save: Option<PathBuf>,
+    /// How many seconds apart the world is saved, on top of when the Server shuts down.
+    pub save_interval: f32,
+    /// Where players' accounts, and their characters, are kept.
+    /// If none is given, they're forgotten when the Server shuts down.
+    pub accounts: Option<PathBuf>,
+    /// How many seconds a player who's lost their connection is kept around,
+    /// in case they come back. If this is zero, they're cleared away right away.
+    pub linger: f32,
+    /// How many tiles away players can be and still hear each other chat.
+    /// If none is given, everyone hears everything.
+    pub chat_radius: Option<f32>,
+}
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:3012".parse().unwrap(),
+            tick_rate: comn::Tick::PER_SECOND,
+            log_level: LevelFilter::Debug,
+            world_size: 48,
+            interest_radius: 15.0,
+            spawn_point: None,
+            seed: rand::random(),
+            cave: CaveConfig::default(),
+            map: None,
+            save: None,
+            save_interval: 60.0,
+            accounts: None,
+            linger: 30.0,
+            chat_radius: None,
+        }
+    }
+}
+impl ServerConfig {
+    /// Reads the config file and command line arguments, panicking if either is bad.
+    pub fn from_args() -> Self {
+        let opts = Opts::from_args();
+
+        let mut config = match &opts.config {
+            Some(path) => Self::from_file(path),
+            None => {
+                let path = PathBuf::from(DEFAULT_PATH);
+                if path.exists() {
+                    Self::from_file(&path)
+                } else {
+                    Self::default()
+                }
+            }
+        };
+
+        if let Some(bind) = opts.bind {
+            config.bind = bind;
+        }
+        if let Some(tick_rate) = opts.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+        if let Some(log_level) = opts.log_level {
+            config.log_level = log_level;
+        }
+        if let Some(world_size) = opts.world_size {
+            config.world_size = world_size;
+        }
+        if let Some(interest_radius) = opts.interest_radius {
+            config.interest_radius = interest_radius;
+        }
+        if let Some(spawn_point) = opts.spawn_point {
+            config.spawn_point = Some(spawn_point);
+        }
+        if let Some(seed) = opts.seed {
+            config.seed = seed;
+        }
+        if let Some(map) = opts.map {
+            config.map = Some(map);
+        }
+        if let Some(save) = opts.save {
+            config.save = Some(save);
+        }
+        if let Some(accounts) = opts.accounts {
+            config.accounts = Some(accounts);
+        }
+        if let Some(linger) = opts.linger {
+            config.linger = linger;
+        }
+        if let Some(chat_radius) = opts.chat_radius {
+            config.chat_radius = Some(chat_radius);
+        }
+
+        if config.tick_rate.is_nan() || config.tick_rate <= 0.0 {
+            panic!("tick_rate must be above zero, not {}", config.tick_rate);
+        }
+        // any smaller, and there's no room for a Cave inside its walls.
+        if config.world_size < 3 {
+            panic!("world_size must be at least 3, not {}", config.world_size);
+        }
+        if config.interest_radius.is_nan() || config.interest_radius <= 0.0 {
+            panic!(
+                "interest_radius must be above zero, not {}",
+                config.interest_radius
+            );
+        }
+        if config.save_interval.is_nan() || config.save_interval <= 0.0 {
+            panic!(
+                "save_interval must be above zero, not {}",
+                config.save_interval
+            );
+        }
+        if config.linger.is_nan() || config.linger < 0.0 {
+            panic!("linger can't be below zero, not {}", config.linger);
+        }
+        if let Some(radius) = config.chat_radius.filter(|r| r.is_nan() || *r <= 0.0) {
+            panic!("chat_radius must be above zero, not {}", radius);
+        }
+
+        config
+    }
+
+    fn from_file(path: &PathBuf) -> Self {
+        let text = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Couldn't read config file {}: {}", path.display(), e));
+
+        toml::from_str(&text)
+            .unwrap_or_else(|e| panic!("Couldn't parse config file {}: {}", path.display(), e))
+    }
+
+    #[inline]
+    pub fn spawn_point(&self) -> Iso2 {
+        match self.spawn_point {
+            Some([x, y]) => Iso2::translation(x, y),
+            None => Iso2::new(Cave::center(self.world_size as usize), 0.0),
+        }
+    }
+}
+
+/// If no config file is specified, this one is used if it's there.
+const DEFAULT_PATH: &str = "serv.toml";
+
+#[derive(StructOpt)]
+#[structopt(name = "serv", about = "Serves a game of storked.")]
+/// These override whatever's in the config file.
+struct Opts {
+    /// TOML file to read the rest of the configuration from [default: serv.toml, if it exists]
+    #[structopt(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Address to listen for clients on
+    #[structopt(short, long)]
+    bind: Option<SocketAddr>,
+
+    /// Times the game loop runs each second
+    #[structopt(long)]
+    tick_rate: Option<f32>,
+
+    /// One of off, error, warn, info, debug or trace
+    #[structopt(long)]
+    log_level: Option<LevelFilter>,
+
+    /// Tiles wide and tall the world is
+    #[structopt(long)]
+    world_size: Option<u32>,
+
+    /// World units away from their player things can be before a client stops hearing about them
+    #[structopt(long)]
+    interest_radius: Option<f32>,
+
+    /// Where new players spawn, as x,y
+    #[structopt(long, parse(try_from_str = parse_point))]
+    spawn_point: Option<[f32; 2]>,
+
+    /// Seed to generate the world from, to get the same one as last time [default: random]
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// RON file to load the world from, instead of generating one
+    #[structopt(short, long, parse(from_os_str))]
+    map: Option<PathBuf>,
+
+    /// RON file to keep the world in between restarts; it's loaded if it's there
+    #[structopt(short, long, parse(from_os_str))]
+    save: Option<PathBuf>,
+
+    /// RON file to keep players' accounts and characters in
+    #[structopt(short, long, parse(from_os_str))]
+    accounts: Option<PathBuf>,
+
+    /// Seconds to keep a player around after they lose their connection
+    #[structopt(long)]
+    linger: Option<f32>,
+
+    /// Tiles away players can be and still hear each other chat [default: everyone hears everything]
+    #[structopt(long)]
+    chat_radius: Option<f32>,
+}
+
+fn parse_point(s: &str) -> Result<[f32; 2], String> {
+    let mut coords = s.split(',').map(|c| c.trim().parse::<f32>());
+
+    match (coords.next(), coords.next(), coords.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Ok([x, y]),
+        _ => Err(format!("expected a point like 1.0,1.0, not {:?}", s)),
+    }
+}
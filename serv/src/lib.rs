@@ -0,0 +1,248 @@
+#![feature(stmt_expr_attributes)]
+//! Everything the Server needs to run a game, without anything tying it to
+//! a particular way of being run; `main.rs` serves clients over websockets,
+//! but tests can just as well build one, connect to it over a Loopback,
+//! and step through it one tick at a time.
+use comn::{
+    prelude::*,
+    specs::{self, prelude::*},
+};
+use log::*;
+use specs::WorldExt;
+pub mod accounts;
+pub mod cave;
+pub mod chat;
+mod config;
+pub mod map;
+pub mod net;
+pub mod pickup;
+pub mod save;
+
+pub use config::ServerConfig;
+
+#[derive(Debug, Clone, Default)]
+/// Where new players can be put when they first spawn in; one is picked at random.
+/// If there aren't any, they go to the ServerConfig's spawn point.
+pub struct SpawnPoints(pub Vec<Iso2>);
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The seed the world was generated from, so that it can be made again;
+/// None when it wasn't generated at all, like when it was loaded from a map.
+pub struct WorldSeed(pub Option<u64>);
+
+/// Builds the dispatcher that runs each tick of a game configured like this.
+/// It still has to be set up with a world before it can be run; `Server::new` does that.
+pub fn build_dispatcher<'a, 'b>(config: &ServerConfig) -> Dispatcher<'a, 'b> {
+    let send_pos = net::SendNewPositions::new(config.tick_rate);
+
+    // The server doesn't render anything, so it doesn't run UpdateAnimations either;
+    // that would only flag every Animate as modified, and they'd all get resent every tick.
+    #[rustfmt::skip]
+    let mut builder = DispatcherBuilder::new()
+        .with(pickup::ItemPickupDrop,            "pickup",           &[])
+        .with(comn::phys::Collision,             "collision",        &[])
+        .with(comn::controls::MoveHeadings,      "heading",          &[])
+        .with(net::HandleClientPackets,          "client packets",   &[])
+        .with(net::SendTileUpdates,              "tile updates",     &["client packets"])
+        .with(net::HandleLogins,                 "logins",           &["client packets"])
+        .with(net::SpawnNewPlayers,              "new players",      &["client packets", "logins"])
+        .with(net::ExpireLingering,              "linger",           &["client packets"])
+        .with(net::SendPings,                    "pings",            &["client packets"])
+        .with(chat::HandleChat,                  "chat",             &["client packets"])
+        .with(chat::Who,                         "who",              &["chat"])
+        .with(net::UpdateInterests,              "interests",        &["pickup", "collision", "heading", "new players"])
+        .with(send_pos,                          "send pos",         &["interests"]);
+
+    // everything else that gets sent has to be sent after the entities it's for.
+    let mut sent = net::add_replicators(&mut builder, &["interests"]);
+    sent.push("send pos".to_string());
+    sent.push("tile updates".to_string());
+    sent.push("pings".to_string());
+    sent.push("chat".to_string());
+    sent.push("who".to_string());
+    let sent = sent.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+
+    #[rustfmt::skip]
+    builder
+        .with(net::SendDespawns,                 "send despawns",    &sent)
+        .with(comn::dead::ClearDead,             "clear dead",       &["send despawns"])
+        .with(net::SendSnapshots,                "send snapshots",   &["send despawns"])
+        .build()
+}
+
+/// Fills the world's TileMap with a cave, and the cave with stalagmites and keys; see `cave`.
+/// The same seed always makes the same world; every random choice made here
+/// has to come from `rng`, or that stops being true.
+pub fn generate_world(world: &mut World, seed: u64) {
+    use cave::Cave;
+    use comn::art::{Animate, Appearance, TileMap};
+    use comn::{Cuboid, Hitbox};
+    use net::WithNetId;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (size, spawn_point, config) = {
+        let config = world.read_resource::<ServerConfig>();
+        (
+            config.world_size as usize,
+            config.spawn_point(),
+            config.cave.clone(),
+        )
+    };
+    info!("generating {}x{} world from seed {}", size, size, seed);
+
+    let cave = Cave::generate(size, spawn_point.translation.vector, &config, &mut rng);
+    let mut tiles = TileMap::new(size, size);
+
+    for cell in cave.floors() {
+        let loc = Cave::loc(cell);
+
+        tiles.set(
+            cell,
+            Some(if rng.gen() {
+                Appearance::Rock
+            } else {
+                Appearance::SpottedRock
+            }),
+        );
+
+        if cave.near_spawn(cell, config.spawn_radius) {
+            continue;
+        }
+
+        let along_wall = cave.neighbors(cell).any(|n| !cave.is_floor(n));
+        if along_wall && rng.gen::<f32>() < config.stalagmites {
+            world
+                .create_entity()
+                .with_net_id()
+                .with(net::Static)
+                .with(Appearance::GleamyStalagmite)
+                .with(Pos::vec(loc + Vec2::y() * 0.75))
+                .with(Hitbox(Cuboid::new(Vec2::new(0.8, 0.5))))
+                .with(Animate::new())
+                .build();
+        } else if rng.gen::<f32>() < config.keys {
+            world
+                .create_entity()
+                .with_net_id()
+                .with(Item::Misc)
+                .with(Appearance::Key)
+                .with(Pos::vec(loc + Vec2::y() * 0.75))
+                .build();
+        }
+    }
+
+    // the walls are drawn as holes, which nobody can walk into.
+    let half_tile = Vec2::repeat(cave::TILE_SIZE / 2.0);
+    for cell in cave.edges() {
+        tiles.set(cell, Some(Appearance::RockHole));
+
+        // Collision puts hitboxes a box's height above their Pos, like the Client
+        // does with sprites, but tiles are drawn centered on their cell, so the
+        // hitbox goes a tile below that, on an entity nobody else needs to hear about.
+        world
+            .create_entity()
+            .with(net::Static)
+            .with(Pos::vec(Cave::loc(cell) + Vec2::y() * cave::TILE_SIZE))
+            .with(Hitbox(Cuboid::new(half_tile)))
+            .build();
+    }
+
+    // Clients are sent the whole TileMap when they join,
+    // so nobody needs to hear about these one at a time.
+    tiles.take_changes();
+    world.insert(tiles);
+    world.insert(SpawnPoints(vec![spawn_point]));
+    world.insert(WorldSeed(Some(seed)));
+}
+
+/// A world, and the dispatcher that runs it.
+pub struct Server {
+    pub world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+}
+impl Server {
+    /// A Server with nothing in its world yet, talking to its clients over `transport`.
+    pub fn new(config: ServerConfig, transport: impl net::Transport + 'static) -> Self {
+        let mut world = specs::World::new();
+        world.insert(comn::Fps(config.tick_rate));
+        world.insert(net::InterestRadius(config.interest_radius));
+        world.insert(net::ConnectionManager::new(transport));
+        world.insert(match &config.accounts {
+            Some(path) => accounts::Accounts::load(path).unwrap_or_else(|e| {
+                panic!("Couldn't load accounts from {}: {}", path.display(), e)
+            }),
+            None => accounts::Accounts::default(),
+        });
+
+        let mut dispatcher = build_dispatcher(&config);
+        world.insert(config);
+        dispatcher.setup(&mut world);
+
+        Self { world, dispatcher }
+    }
+
+    /// Runs this many ticks, one right after the other.
+    pub fn step(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.dispatcher.dispatch(&mut self.world);
+            self.world.maintain();
+            self.world.write_resource::<comn::Tick>().0 += 1;
+        }
+    }
+
+    /// Writes everything that should outlive the Server to `path`; see `save`.
+    /// The characters of everyone playing are written down with their accounts as well.
+    /// Failing to save isn't worth stopping the game over, so that's only logged.
+    pub fn save(&self, path: &std::path::Path) {
+        accounts::store_everyone(&self.world);
+        match save::Save::take(&self.world).write(path) {
+            Ok(()) => debug!("saved the world to {}", path.display()),
+            Err(e) => error!("Couldn't save the world to {}: {}", path.display(), e),
+        }
+    }
+
+    /// Runs a tick whenever it's time to, until someone hits Ctrl-C,
+    /// saving every so often if there's somewhere to save to.
+    pub fn run(mut self) -> ! {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        let (tick_rate, save_path, save_interval) = {
+            let config = self.world.read_resource::<ServerConfig>();
+            (config.tick_rate, config.save.clone(), config.save_interval)
+        };
+        let save_ticks = ((save_interval * tick_rate).round() as u64).max(1);
+        let mut fixedstep = fixedstep::FixedStep::start(tick_rate as f64);
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        ctrlc::set_handler({
+            let stopping = stopping.clone();
+            move || stopping.store(true, Ordering::SeqCst)
+        })
+        .expect("Couldn't listen for Ctrl-C");
+
+        info!("starting game loop!");
+        loop {
+            while fixedstep.update() {
+                self.step(1);
+
+                let comn::Tick(tick) = *self.world.read_resource();
+                if let Some(path) = save_path.as_ref().filter(|_| tick % save_ticks == 0) {
+                    self.save(path);
+                }
+            }
+
+            if stopping.load(Ordering::SeqCst) {
+                info!("shutting down!");
+                match &save_path {
+                    Some(path) => self.save(path),
+                    None => accounts::store_everyone(&self.world),
+                }
+                std::process::exit(0);
+            }
+        }
+    }
+}
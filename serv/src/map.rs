@@ -0,0 +1,344 @@
+//! Maps are written by hand in RON, and look something like this:
+//!
+//! ```ron
+//! Map(
+//!     name: "The Antechamber",
+//!     author: "cedric",
+//!     // every character in a layer is looked up in here; spaces are left empty.
+//!     legend: {
+//!         '.': Rock,
+//!         ',': SpottedRock,
+//!         '#': RockHole,
+//!     },
+//!     // tiles in the legend that nobody can walk through.
+//!     walls: "#",
+//!     // each layer is laid over the last, one row after another;
+//!     // wherever a later layer has a tile, it replaces the one underneath.
+//!     layers: [
+//!         [
+//!             "#####",
+//!             "#.,.#",
+//!             "#####",
+//!         ],
+//!     ],
+//!     spawns: [(4.0, 2.0)],
+//!     entities: [
+//!         (appearance: GleamyStalagmite, at: (2.0, 2.75), hitbox: Some((0.8, 0.5))),
+//!         (appearance: Key, at: (6.0, 2.75), item: Some(Misc)),
+//!     ],
+//! )
+//! ```
+//!
+//! The layers all end up in the world's TileMap, so tiles are `TILE_SIZE` apart
+//! the same as in generated worlds, and everything else is placed in world coordinates.
+use crate::cave::TILE_SIZE;
+use crate::net::{self, WithNetId};
+use crate::{SpawnPoints, WorldSeed};
+use comn::art::{Animate, Appearance, TileMap, SPRITESHEETS};
+use comn::prelude::*;
+use comn::specs::{prelude::*, WorldExt};
+use comn::{Cuboid, Hitbox};
+// util
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::{fmt, fs, marker::PhantomData, path::Path};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// See the module level documentation.
+pub struct Map {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    pub legend: HashMap<char, Appearance>,
+    #[serde(default)]
+    pub walls: String,
+    pub layers: Vec<Vec<String>>,
+    pub spawns: Vec<(f32, f32)>,
+    #[serde(default, deserialize_with = "entities")]
+    pub entities: Vec<Placement>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Something on a map that isn't a tile.
+pub struct Placement {
+    #[serde(deserialize_with = "appearance")]
+    pub appearance: Appearance,
+    pub at: (f32, f32),
+    /// The half extents of its Hitbox, if it has one.
+    #[serde(default)]
+    pub hitbox: Option<(f32, f32)>,
+    /// Things that are Items can be picked up; nothing else ever moves.
+    #[serde(default, deserialize_with = "item")]
+    pub item: Option<Item>,
+}
+
+// Errors from the fields of a Placement start with a dot and the field's name,
+// so that once `entities` puts which one it was in front, they read like
+// "entities[2].appearance: unknown variant `Boulder`".
+fn appearance<'de, D: Deserializer<'de>>(d: D) -> Result<Appearance, D::Error> {
+    Appearance::deserialize(d).map_err(|e| de::Error::custom(format!(".appearance: {}", e)))
+}
+
+fn item<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Item>, D::Error> {
+    Option::<Item>::deserialize(d).map_err(|e| de::Error::custom(format!(".item: {}", e)))
+}
+
+fn entities<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Placement>, D::Error> {
+    d.deserialize_seq(Indexed("entities", PhantomData))
+}
+
+/// Deserializes a list, saying which element of it was wrong if one is.
+struct Indexed<T>(&'static str, PhantomData<T>);
+impl<'de, T: Deserialize<'de>> Visitor<'de> for Indexed<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of {}", self.0)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let mut items = Vec::new();
+        loop {
+            match seq.next_element() {
+                Ok(Some(item)) => items.push(item),
+                Ok(None) => return Ok(items),
+                Err(e) => {
+                    let e = e.to_string();
+                    let sep = if e.starts_with('.') { "" } else { ": " };
+                    return Err(de::Error::custom(format!(
+                        "{}[{}]{}{}",
+                        self.0,
+                        items.len(),
+                        sep,
+                        e
+                    )));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Why a map couldn't be loaded.
+pub enum MapError {
+    Io(std::io::Error),
+    /// Something in the file isn't what it should be; line and column start at 1.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A layer uses a character that isn't in the legend.
+    /// Layers, rows and columns all count from 1, like lines do;
+    /// `at` is the line and column in the file, if the row could be found there.
+    UnknownTile {
+        layer: usize,
+        row: usize,
+        column: usize,
+        tile: char,
+        at: Option<(usize, usize)>,
+    },
+    /// Something else about the map doesn't make sense.
+    Invalid(String),
+}
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "couldn't read map: {}", e),
+            MapError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            MapError::UnknownTile {
+                layer,
+                row,
+                column,
+                tile,
+                at,
+            } => {
+                if let Some((line, column)) = at {
+                    write!(f, "line {}, column {}: ", line, column)?;
+                }
+                write!(
+                    f,
+                    "{:?} isn't in the legend (layer {}, row {}, column {})",
+                    tile, layer, row, column
+                )
+            }
+            MapError::Invalid(why) => write!(f, "{}", why),
+        }
+    }
+}
+impl std::error::Error for MapError {}
+
+/// The line and column, counting from 1, of where `remainder` starts in `text`.
+fn position(text: &str, remainder: &str) -> (usize, usize) {
+    let read = &text[..text.len() - remainder.len()];
+    let line = read.matches('\n').count() + 1;
+    let column = read.len() - read.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Self, MapError> {
+        Self::parse(&fs::read_to_string(path).map_err(MapError::Io)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MapError> {
+        // ron only says where it was for some errors, so for the others
+        // (like an Appearance that doesn't exist) we work out where it stopped reading.
+        let position = |remainder: &str| position(text, remainder);
+        let syntax = |(line, column), message: String| MapError::Syntax {
+            line,
+            column,
+            message,
+        };
+
+        let mut de = ron::de::Deserializer::from_str(text)
+            .map_err(|e| syntax(position(text), e.to_string()))?;
+        let map = Map::deserialize(&mut de)
+            .and_then(|map| de.end().map(|()| map))
+            .map_err(|e| match e {
+                ron::de::Error::Parser(e, pos) => syntax((pos.line, pos.col), format!("{:?}", e)),
+                e => syntax(position(&de.remainder()), e.to_string()),
+            })?;
+
+        map.validate(text)?;
+        Ok(map)
+    }
+
+    /// Makes sure everything a map refers to is actually there.
+    /// `text` is what the map was parsed from, to say where any problems are.
+    fn validate(&self, text: &str) -> Result<(), MapError> {
+        for (layer, rows) in self.layers.iter().enumerate() {
+            for (row, tiles) in rows.iter().enumerate() {
+                for (column, tile) in tiles.chars().enumerate() {
+                    if tile != ' ' && !self.legend.contains_key(&tile) {
+                        return Err(MapError::UnknownTile {
+                            layer: layer + 1,
+                            row: row + 1,
+                            column: column + 1,
+                            tile,
+                            at: self
+                                .find_row(text, layer, row)
+                                .map(|(line, start)| (line, start + column)),
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some(wall) = self.walls.chars().find(|c| !self.legend.contains_key(c)) {
+            return Err(MapError::Invalid(format!(
+                "walls has {:?}, which isn't in the legend",
+                wall
+            )));
+        }
+
+        if self.spawns.is_empty() {
+            return Err(MapError::Invalid("a map needs at least one spawn".into()));
+        }
+
+        Ok(())
+    }
+
+    /// Where the first tile of `layers[layer][row]` is in `text`, as a line and column.
+    /// Each row's string is looked for after the last one's, so that identical rows
+    /// are told apart; if one can't be found (say it has escapes in it), neither can the rest.
+    fn find_row(&self, text: &str, layer: usize, row: usize) -> Option<(usize, usize)> {
+        let mut at = text.find("layers")?;
+        for (l, rows) in self.layers.iter().enumerate() {
+            for (r, tiles) in rows.iter().enumerate() {
+                let quoted = format!("{:?}", tiles);
+                at += text[at..].find(&quoted)?;
+                if (l, r) == (layer, row) {
+                    // just past the opening quote
+                    return Some(position(text, &text[at + 1..]));
+                }
+                at += quoted.len();
+            }
+        }
+        None
+    }
+
+    /// How many tiles wide and tall the widest and tallest layers are.
+    pub fn size(&self) -> (usize, usize) {
+        let rows = self.layers.iter().flatten();
+        let width = rows.map(|row| row.chars().count()).max().unwrap_or(0);
+        let height = self.layers.iter().map(Vec::len).max().unwrap_or(0);
+        (width, height)
+    }
+
+    /// Puts everything on this map into the world.
+    pub fn build(&self, world: &mut World) {
+        let half_tile = Vec2::repeat(TILE_SIZE / 2.0);
+        let (width, height) = self.size();
+        let mut tiles = TileMap::new(width, height);
+        let mut walls = HashSet::new();
+
+        for rows in &self.layers {
+            for (y, row) in rows.iter().enumerate() {
+                for (x, tile) in row.chars().enumerate() {
+                    if let Some(appearance) = self.legend.get(&tile) {
+                        tiles.set((x, y), Some(appearance.clone()));
+                        if self.walls.contains(tile) {
+                            walls.insert((x, y));
+                        } else {
+                            walls.remove(&(x, y));
+                        }
+                    }
+                }
+            }
+        }
+
+        // see generate_world for why these are their own entities.
+        for cell in walls {
+            world
+                .create_entity()
+                .with(net::Static)
+                .with(Pos::vec(TileMap::loc(cell) + Vec2::y() * TILE_SIZE))
+                .with(Hitbox(Cuboid::new(half_tile)))
+                .build();
+        }
+
+        tiles.take_changes();
+        world.insert(tiles);
+
+        for placement in &self.entities {
+            let (x, y) = placement.at;
+            let mut builder = world
+                .create_entity()
+                .with_net_id()
+                .with(placement.appearance.clone())
+                .with(Pos::vec(Vec2::new(x, y)));
+
+            if SPRITESHEETS.contains_key(&placement.appearance) {
+                builder = builder.with(Animate::new());
+            }
+            if let Some((w, h)) = placement.hitbox {
+                builder = builder.with(Hitbox(Cuboid::new(Vec2::new(w, h))));
+            }
+            builder = match &placement.item {
+                Some(item) => builder.with(item.clone()),
+                None => builder.with(net::Static),
+            };
+
+            builder.build();
+        }
+
+        world.insert(SpawnPoints(
+            self.spawns
+                .iter()
+                .map(|&(x, y)| Iso2::translation(x, y))
+                .collect(),
+        ));
+        world.insert(WorldSeed(None));
+    }
+}
@@ -1,173 +1,116 @@
-// networking
-use std::net::{SocketAddr, TcpListener};
-use tungstenite::{accept_hdr, handshake::server::Request, Message};
+use super::transport::{ClientEvent, ToClient, Transport, WebSocketTransport};
+use std::net::SocketAddr;
 // util
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use log::*;
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-    thread::spawn,
-};
+use std::{collections::HashMap, sync::Mutex, time::Instant};
 // reexports/main lib
-use comn::{rmps, specs, Dead, NetComponent, NetMessage};
+use comn::net::ResumeToken;
+use comn::{specs, NetComponent, NetComponentKind, NetId, ServerMessage, Snapshot, Tick};
 
 pub struct ConnectionManager {
-    pub from_clients: Receiver<(SocketAddr, NetMessage)>,
-    pub to_clients: Sender<(SocketAddr, NetMessage)>,
-    pub addr_to_ent: HashMap<SocketAddr, u32>,
+    /// How we actually talk to the clients.
+    transport: Box<dyn Transport>,
+    /// Only clients that have said Hello are in here.
+    pub addr_to_ent: HashMap<SocketAddr, specs::Entity>,
+    /// Which player each ResumeToken gives back; every Client has one,
+    /// and so does every player who's Lingering.
+    pub resume_tokens: HashMap<ResumeToken, specs::Entity>,
+    /// The messages that will go out in each client's next Snapshot.
+    outbox: Mutex<HashMap<SocketAddr, Vec<ServerMessage>>>,
+    /// The clients to hang up on once their last Snapshot has gone out.
+    closing: Vec<SocketAddr>,
+    /// Pings are timed from when this was made; see `uptime`.
+    started: Instant,
 }
 
 impl ConnectionManager {
-    fn new() -> Self {
-        let (to_thread, from_clients) = unbounded();
-        let (to_clients, from_thread) = unbounded();
-
-        let msgs_for_srv = to_thread.clone();
-        let msgs_to_send = from_thread.clone();
-        spawn(move || {
-            let server = TcpListener::bind("127.0.0.1:3012").unwrap();
-
-            let channels: Arc<Mutex<HashMap<SocketAddr, Sender<NetMessage>>>> =
-                Arc::new(Mutex::new(HashMap::new()));
-
-            spawn({
-                let channels = channels.clone();
-
-                move || loop {
-                    while let Ok((to_addr, msg)) = msgs_to_send.recv() {
-                        // the only other time a lock on this mutex can occur is when
-                        // someone is connecting, so theoretically there could be a hitch
-                        // then.
-                        if let Err(e) =
-                            channels.lock().expect("couldn't get channels map")[&to_addr].send(msg)
-                        {
-                            trace!("couldn't send message to thread for websocket: {}", e);
-                        }
-                    }
-                }
-            });
-
-            for stream in server.incoming() {
-                debug!("New client connected!");
-                let stream = stream.expect("couldn't establish stream.");
-                let addr = stream
-                    .peer_addr()
-                    .expect("unable to determine address of new connector");
-
-                let msgs_for_srv = msgs_for_srv.clone();
-                let (channels_s, msgs_to_send) = unbounded();
-                channels
-                    .lock()
-                    .expect("Couldn't get channels map to insert new websocket.")
-                    .insert(addr.clone(), channels_s);
-
-                trace!("Sender inserted into channel recorder!");
-
-                spawn(move || {
-                    let callback = |req: &Request| {
-                        println!("Received a new ws handshake");
-                        println!("The request's path is: {}", req.path);
-                        println!("The request's headers are:");
-                        for &(ref header, _ /* value */) in req.headers.iter() {
-                            println!("* {}", header);
-                        }
-
-                        // Let's add an additional header to our response to the client.
-                        let extra_headers = vec![
-                            (String::from("MyCustomHeader"), String::from(":)")),
-                            (
-                                String::from("SOME_TUNGSTENITE_HEADER"),
-                                String::from("header_value"),
-                            ),
-                        ];
-                        Ok(Some(extra_headers))
-                    };
-
-                    // this lets us try to get messages from the websocket without blocking.
-                    // (so we can send output too)
-                    stream.set_nonblocking(true).expect("can't set unblocking");
-
-                    let mut websocket =
-                        accept_hdr(stream, callback).expect("couldn't accept handshake");
-
-                    // tell the game thread that a connection with this client has been established.
-                    msgs_for_srv
-                        .send((
-                            addr.clone(),
-                            // this 0 is purely filler, clients dont get to pick their ent ofc
-                            NetMessage::NewEnt(0),
-                        ))
-                        .expect(
-                            "Couldn't send connection established NewEnt message over channel!",
-                        );
-
-                    'poll: loop {
-                        if let Ok(Message::Binary(data)) = websocket.read_message() {
-                            msgs_for_srv
-                                .send((
-                                    addr.clone(),
-                                    rmps::from_read_ref(&data)
-                                        .expect("Couldn't decode NetMessage bytes!"),
-                                ))
-                                .expect("Couldn't send NetMessage over channel!");
-                        }
-
-                        while let Ok(msg) = msgs_to_send.try_recv() {
-                            trace!("got {:#?} for {:#?}", msg, addr);
-
-                            // if the call succeeds, all is well, but if it fails we need
-                            // to tell the game loop that happened and then stop listening for
-                            // their messages because they've probably logged off.
-                            if let Err(_) = websocket.write_message(Message::Binary(
-                                rmps::encode::to_vec(&msg).expect("Couldn't encode NetMessage!"),
-                            )) {
-                                // tell the game loop they ded
-                                msgs_for_srv
-                                    .send((addr.clone(), NetMessage::InsertComp(0, Dead.into())))
-                                    .expect("Couldn't send log-off message over channel!");
-
-                                // stop listening for their messages
-                                break 'poll;
-                            }
-                        }
-                    }
-                });
-            }
-        });
-
+    pub fn new(transport: impl Transport + 'static) -> Self {
         Self {
-            from_clients,
-            to_clients,
+            transport: Box::new(transport),
             addr_to_ent: HashMap::new(),
+            resume_tokens: HashMap::new(),
+            outbox: Mutex::new(HashMap::new()),
+            closing: Vec::new(),
+            started: Instant::now(),
         }
     }
 
+    /// How many seconds it's been since this was made.
+    /// Pings to the clients are timed with this; see `comn::ping`.
+    pub fn uptime(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
     #[inline]
-    pub fn send(&self, addr: SocketAddr, msg: NetMessage) {
-        self.to_clients
-            .send((addr, msg))
-            .expect("Couldn't send NetMessage to to_clients channel!");
+    /// The next thing that happened with one of the clients, if anything has.
+    pub fn recv(&self) -> Option<(SocketAddr, ClientEvent)> {
+        self.transport.recv()
     }
 
     #[inline]
-    pub fn new_ent(&self, addr: SocketAddr, ent: specs::Entity) {
-        self.send(addr, NetMessage::NewEnt(ent.id()));
+    /// Queues up a message to go out in this client's next Snapshot.
+    pub fn send(&self, addr: SocketAddr, msg: ServerMessage) {
+        self.outbox
+            .lock()
+            .expect("Couldn't lock outbox to queue ServerMessage!")
+            .entry(addr)
+            .or_insert_with(Vec::new)
+            .push(msg);
+    }
+
+    /// Sends out everything that's been queued up this tick,
+    /// one Snapshot for each client that has something to hear.
+    pub fn flush(&mut self, tick: Tick) {
+        let outbox = self
+            .outbox
+            .get_mut()
+            .expect("Couldn't lock outbox to flush Snapshots!");
+
+        for (addr, messages) in outbox.drain() {
+            self.transport
+                .send(addr, ToClient::Snapshot(Snapshot { tick, messages }));
+        }
+
+        for addr in self.closing.drain(..) {
+            self.transport.send(addr, ToClient::Close);
+        }
+
+        self.transport.flush();
+    }
+
+    /// Tells this client why they're being turned away,
+    /// then hangs up on them once that's been sent.
+    pub fn reject(&mut self, addr: SocketAddr, reason: String) {
+        self.send(addr, ServerMessage::Rejected(reason));
+        self.closing.push(addr);
     }
 
     #[inline]
-    pub fn insert_comp<C: Into<NetComponent>>(
-        &self,
-        addr: SocketAddr,
-        ent: specs::Entity,
-        comp: C,
-    ) {
-        self.send(addr, NetMessage::InsertComp(ent.id(), comp.into()));
+    pub fn new_ent(&self, addr: SocketAddr, id: NetId) {
+        self.send(addr, ServerMessage::NewEnt(id));
+    }
+
+    #[inline]
+    pub fn insert_comp<C: Into<NetComponent>>(&self, addr: SocketAddr, id: NetId, comp: C) {
+        self.send(addr, ServerMessage::InsertComp(id, comp.into()));
+    }
+
+    #[inline]
+    pub fn remove_ent(&self, addr: SocketAddr, id: NetId) {
+        self.send(addr, ServerMessage::RemoveEnt(id));
+    }
+
+    #[inline]
+    pub fn remove_comp(&self, addr: SocketAddr, id: NetId, kind: NetComponentKind) {
+        self.send(addr, ServerMessage::RemoveComp(id, kind));
     }
 }
 
 impl Default for ConnectionManager {
     fn default() -> Self {
-        Self::new()
+        let addr = crate::ServerConfig::default().bind;
+        let transport = WebSocketTransport::bind(&addr)
+            .unwrap_or_else(|e| panic!("Couldn't listen for clients on {}: {}", addr, e));
+
+        Self::new(transport)
     }
 }
@@ -0,0 +1,34 @@
+// our code
+use super::prelude::*;
+use comn::specs::prelude::*;
+use comn::Dead;
+// crates
+use log::*;
+
+/// This system tells clients to delete the entities that ClearDead
+/// is about to clear away, so that they don't hang around on the clients forever.
+/// It also frees up their NetIds.
+pub struct SendDespawns;
+impl<'a> System<'a> for SendDespawns {
+    type SystemData = (
+        Read<'a, ConnectionManager>,
+        Write<'a, NetIds>,
+        ReadStorage<'a, Client>,
+        WriteStorage<'a, Interest>,
+        ReadStorage<'a, NetId>,
+        ReadStorage<'a, Dead>,
+    );
+
+    fn run(&mut self, (cm, mut ids, clients, mut interests, net_ids, dead): Self::SystemData) {
+        for (&id, _) in (&net_ids, &dead).join() {
+            trace!("telling everyone that entity {:?} died", id);
+            // no sense telling the dead about their own demise.
+            for (Client(addr), interest, _) in (&clients, &mut interests, !&dead).join() {
+                if interest.known.remove(&id) {
+                    cm.remove_ent(*addr, id);
+                }
+            }
+            ids.free(id);
+        }
+    }
+}
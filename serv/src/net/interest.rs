@@ -0,0 +1,98 @@
+// our code
+use super::prelude::*;
+use comn::item::Inventory;
+use comn::specs::prelude::*;
+use comn::{Dead, Pos};
+// crates
+use log::*;
+use specs_derive::Component;
+use std::collections::HashSet;
+
+/// How far away from their player (in world units) an entity can be
+/// before a client stops being told about it.
+pub struct InterestRadius(pub f32);
+impl Default for InterestRadius {
+    fn default() -> Self {
+        InterestRadius(15.0)
+    }
+}
+
+#[derive(Component, Clone, Debug, Default)]
+#[storage(DenseVecStorage)]
+/// Every client has one of these, to keep track of
+/// which entities it's been told about.
+pub struct Interest {
+    /// The NetIds of every entity this client knows about.
+    pub known: HashSet<NetId>,
+    /// The NetIds of the entities this client only found out about this tick.
+    /// The systems that replicate components send them in full for these,
+    /// and skip any changes to them.
+    pub entered: HashSet<NetId>,
+}
+impl Interest {
+    /// Whether or not this client should be told about changes to the entity with this id.
+    #[inline]
+    pub fn follows(&self, id: &NetId) -> bool {
+        self.known.contains(id) && !self.entered.contains(id)
+    }
+}
+
+/// This system figures out which entities each client should know about:
+/// those within InterestRadius of their player, their player itself,
+/// and anything in their player's Inventory.
+/// Entities that come into a client's interest are sent to them as NewEnts,
+/// and those that leave it are sent as RemoveEnts.
+pub struct UpdateInterests;
+impl<'a> System<'a> for UpdateInterests {
+    type SystemData = (
+        Read<'a, ConnectionManager>,
+        Read<'a, InterestRadius>,
+        Entities<'a>,
+        ReadStorage<'a, Client>,
+        WriteStorage<'a, Interest>,
+        ReadStorage<'a, NetId>,
+        ReadStorage<'a, Pos>,
+        ReadStorage<'a, Inventory>,
+        ReadStorage<'a, Dead>,
+    );
+
+    fn run(
+        &mut self,
+        (cm, radius, ents, clients, mut interests, net_ids, poses, invs, dead): Self::SystemData,
+    ) {
+        let radius_squared = radius.0 * radius.0;
+
+        for (player, Client(addr), interest, _) in (&*ents, &clients, &mut interests, !&dead).join()
+        {
+            let mut interesting = HashSet::new();
+
+            if let Some(&id) = net_ids.get(player) {
+                interesting.insert(id);
+            }
+            if let Some(inv) = invs.get(player) {
+                interesting.extend(inv.loose().chain(inv.reserved()).filter_map(|(_, id)| *id));
+            }
+            if let Some(Pos(center)) = poses.get(player) {
+                for (Pos(iso), &id, _) in (&poses, &net_ids, !&dead).join() {
+                    let to = iso.translation.vector - center.translation.vector;
+                    if to.magnitude_squared() < radius_squared {
+                        interesting.insert(id);
+                    }
+                }
+            }
+
+            for &id in interest.known.difference(&interesting) {
+                trace!("{:?} left the interest of Client {}", id, player.id());
+                cm.remove_ent(*addr, id);
+            }
+
+            interest.entered = interesting.difference(&interest.known).cloned().collect();
+            for &id in interest.entered.iter() {
+                trace!("{:?} entered the interest of Client {}", id, player.id());
+                cm.new_ent(*addr, id);
+            }
+
+            interest.known = interesting;
+        }
+    }
+}
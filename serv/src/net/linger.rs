@@ -0,0 +1,54 @@
+// our code
+use super::prelude::*;
+use crate::accounts;
+use comn::specs::prelude::*;
+use comn::{Dead, Tick};
+// crates
+use log::*;
+use specs_derive::Component;
+
+#[derive(Component, Clone, Debug)]
+#[storage(DenseVecStorage)]
+/// Players who've lost their connection have this instead of a Client.
+/// They stay in the world just as they were until this tick,
+/// so that they can pick up where they left off if they come back in time;
+/// see `comn::net::ResumeToken`.
+pub struct Lingering {
+    pub until: Tick,
+}
+
+/// Clears away a player who's gone for good, storing their character
+/// with their account first, if they've logged into one.
+pub fn depart(cm: &mut ConnectionManager, lu: &LazyUpdate, ent: Entity) {
+    cm.resume_tokens.retain(|_, &mut e| e != ent);
+    // their character goes with their account, items and all.
+    lu.exec_mut(move |world| accounts::log_out(world, ent));
+    // SendDespawns will tell everyone else they're gone.
+    lu.insert(ent, Dead);
+}
+
+/// This system clears away the players who haven't come back in time.
+pub struct ExpireLingering;
+impl<'a> System<'a> for ExpireLingering {
+    type SystemData = (
+        Write<'a, ConnectionManager>,
+        Read<'a, LazyUpdate>,
+        Read<'a, Tick>,
+        Entities<'a>,
+        WriteStorage<'a, Lingering>,
+    );
+
+    fn run(&mut self, (mut cm, lu, tick, ents, mut lingering): Self::SystemData) {
+        let expired = (&*ents, &lingering)
+            .join()
+            .filter(|(_, Lingering { until })| *until <= *tick)
+            .map(|(ent, _)| ent)
+            .collect::<Vec<_>>();
+
+        for ent in expired {
+            info!("Client {} didn't come back in time", ent.id());
+            lingering.remove(ent);
+            depart(&mut cm, &lu, ent);
+        }
+    }
+}
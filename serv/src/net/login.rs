@@ -1,67 +1,132 @@
 // our code
+use super::linger::{depart, Lingering};
 use super::prelude::*;
+use crate::accounts::{Accounts, LoggedIn, LoginError};
 use comn::prelude::*;
 use comn::specs::prelude::*;
+use comn::ServerMessage;
 // crates
 use log::*;
+use specs_derive::Component;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
 
-/// This system sends the world to all clients with the LoggingIn component.
-pub struct SendWorldToNewPlayers;
-impl<'a> System<'a> for SendWorldToNewPlayers {
+/// How many logins are checked each tick, at most. Checking a password is slow
+/// on purpose, so a flood of them would hold up the game for everyone.
+pub const LOGINS_PER_TICK: usize = 2;
+/// How many times a connection can fail to log in before it's hung up on.
+pub const MAX_LOGIN_FAILURES: u32 = 5;
+
+/// Someone's try at logging in, waiting to be checked.
+/// This isn't Debug, so that the password can't end up in the logs.
+pub struct PendingLogin {
+    pub ent: Entity,
+    pub addr: SocketAddr,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Default)]
+/// The logins waiting to be checked, oldest first.
+/// HandleClientPackets only lets each Client have one in here at a time,
+/// so there can't ever be more of these than there are Clients.
+pub struct PendingLogins(pub VecDeque<PendingLogin>);
+
+#[derive(Component, Clone, Debug, Default)]
+#[storage(DenseVecStorage)]
+/// How many times this Client has failed to log in; see MAX_LOGIN_FAILURES.
+pub struct LoginFailures(pub u32);
+
+/// This system checks up to LOGINS_PER_TICK of the PendingLogins each tick,
+/// logging in those who get their password right and telling the rest why not.
+pub struct HandleLogins;
+impl<'a> System<'a> for HandleLogins {
     type SystemData = (
-        // things we need to do networking
-        Read<'a, ConnectionManager>,
-        WriteStorage<'a, LoggingIn>,
-        ReadStorage<'a, Client>,
-        // things we need to tell new players about
+        Write<'a, ConnectionManager>,
+        Read<'a, LazyUpdate>,
         Entities<'a>,
-        ReadStorage<'a, comn::Hitbox>,
-        ReadStorage<'a, comn::art::Appearance>,
-        ReadStorage<'a, comn::art::Tile>,
-        ReadStorage<'a, comn::art::Animate>,
-        ReadStorage<'a, Item>,
-        ReadStorage<'a, Pos>,
+        Write<'a, PendingLogins>,
+        Write<'a, Accounts>,
+        ReadStorage<'a, Client>,
+        WriteStorage<'a, LoggedIn>,
+        WriteStorage<'a, Lingering>,
+        WriteStorage<'a, LoginFailures>,
     );
 
     fn run(
         &mut self,
-        (cm, mut logging_ins, clients, ents, hitboxes, appearances, tiles, animates, items, isos): Self::SystemData,
+        (
+            mut cm,
+            lu,
+            ents,
+            mut logins,
+            mut accounts,
+            clients,
+            mut logged_in,
+            mut lingering,
+            mut failures,
+        ): Self::SystemData,
     ) {
-        for (_, Client(addr)) in (logging_ins.drain(), &clients).join() {
-            debug!("We're about to tell a new player about the world.");
-            // tell them about each new entity they need to add, and about
-            // some crucial components it has.
-            for (iso, ent, hitbox, appearance, tile, animate, item) in (
-                &isos,
-                &*ents,
-                hitboxes.maybe(),
-                appearances.maybe(),
-                tiles.maybe(),
-                animates.maybe(),
-                items.maybe(),
-            )
+        let mut checked = 0;
+        while checked < LOGINS_PER_TICK {
+            let PendingLogin {
+                ent,
+                addr,
+                username,
+                password,
+            } = match logins.0.pop_front() {
+                Some(login) => login,
+                None => break,
+            };
+            // they might've left while they were waiting.
+            if clients.get(ent).map(|Client(a)| *a) != Some(addr) {
+                continue;
+            }
+            checked += 1;
+
+            let playing = (&ents, &logged_in)
                 .join()
-            {
-                trace!("telling new player about an existing entity");
-                cm.new_ent(*addr, ent);
-                cm.insert_comp(*addr, ent, iso.clone());
+                .find(|(_, LoggedIn(name))| *name == username)
+                .map(|(ent, _)| ent);
+            let lingerer = playing.filter(|&other| lingering.contains(other));
+            let result = if playing.is_some() && lingerer.is_none() {
+                Err(LoginError::AlreadyPlaying)
+            } else {
+                accounts.log_in(&username, &password)
+            };
 
-                // I should really do all of these using some more macro
-                // abomination on net_component
-                if let Some(hitbox) = hitbox {
-                    cm.insert_comp(*addr, ent, hitbox.clone());
-                }
-                if let Some(appearance) = appearance {
-                    cm.insert_comp(*addr, ent, appearance.clone());
-                }
-                if let Some(item) = item {
-                    cm.insert_comp(*addr, ent, item.clone());
+            match result {
+                Ok(()) => {
+                    info!("Client {} logged in as {}", ent.id(), username);
+                    // whoever left this account behind isn't coming back for it,
+                    // so it's handed over to whoever's logged in now.
+                    if let Some(other) = lingerer {
+                        lingering.remove(other);
+                        depart(&mut cm, &lu, other);
+                    }
+                    logged_in
+                        .insert(ent, LoggedIn(username.clone()))
+                        .expect("Couldn't mark Client as logged in");
+                    cm.send(addr, ServerMessage::LoggedIn(username));
                 }
-                if let Some(animate) = animate {
-                    cm.insert_comp(*addr, ent, animate.clone());
-                }
-                if tile.is_some() {
-                    cm.insert_comp(*addr, ent, comn::art::Tile);
+                Err(e) => {
+                    info!(
+                        "Client {} couldn't log in as {}: {:?}",
+                        ent.id(),
+                        username,
+                        e
+                    );
+                    cm.send(addr, ServerMessage::LoginFailed(e.to_string()));
+
+                    let LoginFailures(failed) = failures
+                        .entry(ent)
+                        .expect("Couldn't get LoginFailures entry for Client")
+                        .or_insert_with(LoginFailures::default);
+                    *failed += 1;
+                    if *failed >= MAX_LOGIN_FAILURES {
+                        warn!("Client {} failed to log in too many times", ent.id());
+                        cm.reject(addr, "Too many failed logins.".to_string());
+                    }
                 }
             }
         }
@@ -72,47 +137,74 @@ pub struct SpawnNewPlayers;
 impl<'a> System<'a> for SpawnNewPlayers {
     type SystemData = (
         Entities<'a>,
-        Read<'a, ConnectionManager>,
         Read<'a, LazyUpdate>,
         WriteStorage<'a, comn::net::SpawnPlayer>,
         ReadStorage<'a, Client>,
+        ReadStorage<'a, LoggedIn>,
+        ReadStorage<'a, Pos>,
+        Write<'a, NetIds>,
+        Read<'a, Accounts>,
+        Read<'a, crate::ServerConfig>,
+        Read<'a, crate::SpawnPoints>,
     );
 
-    fn run(&mut self, (ents, cm, lu, mut players_to_spawn, clients): Self::SystemData) {
+    fn run(
+        &mut self,
+        (
+            ents,
+            lu,
+            mut players_to_spawn,
+            clients,
+            logged_in,
+            poses,
+            mut ids,
+            accounts,
+            config,
+            spawns,
+        ): Self::SystemData,
+    ) {
         use comn::{
             art::{self, Animate, Appearance},
             item, net, Cuboid, Hitbox,
         };
-        for (_, ent, Client(new_player_addr)) in (players_to_spawn.drain(), &*ents, &clients).join()
-        {
-            trace!("spawning new player!");
-            // these are the components the entity will have.
-            let appearance = Appearance::Player;
-            let iso = Pos(Iso2::translation(1.0, 1.0));
-            let animate = Animate::new();
-            let hitbox = Hitbox(Cuboid::new(Vec2::new(0.5, 0.25)));
-
-            // give them player components
-            lu.insert(ent, iso.clone());
-            lu.insert(ent, appearance.clone());
-            lu.insert(ent, animate.clone());
-            lu.insert(ent, hitbox.clone());
-            lu.insert(ent, art::PlayerAnimationController);
-            lu.insert(ent, item::Inventory::character());
+        use rand::seq::SliceRandom;
+        for (_, ent, _) in (players_to_spawn.drain(), &*ents, &clients).join() {
+            let LoggedIn(username) = match logged_in.get(ent) {
+                Some(logged_in) => logged_in,
+                None => {
+                    warn!("Client {} asked to spawn before logging in", ent.id());
+                    continue;
+                }
+            };
+            // spawning twice would leave them with two of everything.
+            if poses.contains(ent) {
+                warn!("{} asked to spawn, but they're already playing", username);
+                continue;
+            }
+            trace!("spawning {}!", username);
 
-            // tell everyone 'bout the new kid on the block
-            for Client(addr) in (&clients).join() {
-                cm.new_ent(*addr, ent);
-                cm.insert_comp(*addr, ent, iso.clone());
-                cm.insert_comp(*addr, ent, appearance.clone());
-                cm.insert_comp(*addr, ent, animate.clone());
-                cm.insert_comp(*addr, ent, hitbox.clone());
-                cm.insert_comp(*addr, ent, art::PlayerAnimationController);
-                if addr == new_player_addr {
-                    cm.insert_comp(*addr, ent, net::LocalPlayer);
-                    debug!("so we did tell them about themself");
+            // they get their character back if they've played before,
+            // otherwise they start out fresh somewhere they can spawn.
+            match accounts.character(username) {
+                Some(character) => character.clone().restore(&ents, &lu, &mut ids, ent),
+                None => {
+                    let spawn = spawns
+                        .0
+                        .choose(&mut rand::thread_rng())
+                        .cloned()
+                        .unwrap_or_else(|| config.spawn_point());
+                    lu.insert(ent, Pos(spawn));
+                    lu.insert(ent, item::Inventory::character());
                 }
             }
+
+            // give them player components;
+            // everyone will be told about these as they're inserted.
+            lu.insert(ent, Appearance::Player);
+            lu.insert(ent, Animate::new());
+            lu.insert(ent, Hitbox(Cuboid::new(Vec2::new(0.5, 0.25))));
+            lu.insert(ent, art::PlayerAnimationController);
+            lu.insert(ent, net::LocalPlayer);
         }
     }
 }
@@ -1,29 +1,90 @@
 mod connection_manager;
+mod despawn;
+mod interest;
+mod linger;
 mod login;
 mod packets;
 mod phys;
+mod ping;
+mod replicate;
+mod snapshot;
+mod tiles;
+pub mod transport;
 
 pub use connection_manager::ConnectionManager;
+pub use transport::{ClientEvent, Loopback, LoopbackClient, Transport, WebSocketTransport};
 
-// main.rs needs to put these Systems in the graph
-pub use login::SendWorldToNewPlayers;
-pub use login::SpawnNewPlayers;
+// build_dispatcher needs to put these Systems in the graph
+pub use despawn::SendDespawns;
+pub use interest::{InterestRadius, UpdateInterests};
+pub use linger::{ExpireLingering, Lingering};
+pub use login::{
+    HandleLogins, LoginFailures, SpawnNewPlayers, LOGINS_PER_TICK, MAX_LOGIN_FAILURES,
+};
 pub use packets::HandleClientPackets;
 pub use phys::SendNewPositions;
+pub use ping::SendPings;
+pub use replicate::add_replicators;
+pub use snapshot::SendSnapshots;
+pub use tiles::SendTileUpdates;
 
 // next we define a few components we'll need to do networking.
-use comn::specs::prelude::*;
+use comn::{net::NetId, specs::prelude::*};
 use specs_derive::Component;
+use std::collections::HashMap;
 
 #[derive(Component, Clone, Debug, Default)]
 #[storage(NullStorage)]
-pub struct LoggingIn;
+/// Entities with this component never move, so clients are only told
+/// about their Pos once, when it's inserted, and never again.
+pub struct Static;
 
 #[derive(Component, Clone, Debug)]
 #[storage(DenseVecStorage)]
 pub struct Client(pub std::net::SocketAddr);
 
+#[derive(Default)]
+/// This hands out NetIds, and remembers which Entity has which one.
+pub struct NetIds {
+    next: u32,
+    ents: HashMap<NetId, Entity>,
+}
+impl NetIds {
+    /// Gives out a NetId that's never been given out before.
+    /// Don't forget to actually put it on the Entity as well!
+    pub fn alloc(&mut self, ent: Entity) -> NetId {
+        let id = NetId(self.next);
+        self.next += 1;
+        self.ents.insert(id, ent);
+        id
+    }
+
+    /// Returns the Entity with the given NetId, if one exists.
+    pub fn entity(&self, id: NetId) -> Option<Entity> {
+        self.ents.get(&id).cloned()
+    }
+
+    /// Forgets about the Entity with the given NetId,
+    /// which should be done once it's been deleted.
+    pub fn free(&mut self, id: NetId) {
+        self.ents.remove(&id);
+    }
+}
+
+/// This makes it easy to give entities NetIds as they're created.
+pub trait WithNetId {
+    fn with_net_id(self) -> Self;
+}
+impl WithNetId for EntityBuilder<'_> {
+    fn with_net_id(self) -> Self {
+        let id = self.world.write_resource::<NetIds>().alloc(self.entity);
+        self.with(id)
+    }
+}
+
 // the submodules can use this to gain access to structs they all need.
 pub mod prelude {
-    pub use super::{Client, ConnectionManager, LoggingIn};
+    pub use super::interest::Interest;
+    pub use super::{Client, ConnectionManager, NetIds, Static};
+    pub use comn::net::NetId;
 }
@@ -1,48 +1,269 @@
+use super::linger::{depart, Lingering};
+use super::login::{PendingLogin, PendingLogins};
 use super::prelude::*;
-use comn::{specs::prelude::*, NetMessage};
+use super::ClientEvent;
+use crate::accounts::LoggedIn;
+use crate::chat::ChatInbox;
+use comn::{
+    controls::InputQueue, net::ResumeToken, ping::Latency, specs::prelude::*, ClientMessage,
+    ServerMessage, Tick, PROTOCOL_VERSION,
+};
 use log::*;
 
+/// What the Server calls itself when it welcomes new Clients.
+const SERVER_NAME: &str = "storked";
+
 pub struct HandleClientPackets;
 impl<'a> System<'a> for HandleClientPackets {
     type SystemData = (
         Write<'a, ConnectionManager>,
+        Write<'a, NetIds>,
         Entities<'a>,
         Read<'a, LazyUpdate>,
+        WriteStorage<'a, NetId>,
         WriteStorage<'a, Client>,
-        WriteStorage<'a, LoggingIn>,
+        WriteStorage<'a, Interest>,
+        WriteStorage<'a, InputQueue>,
+        Read<'a, crate::ServerConfig>,
+        Read<'a, crate::WorldSeed>,
+        Read<'a, comn::art::TileMap>,
+        Write<'a, PendingLogins>,
+        ReadStorage<'a, LoggedIn>,
+        WriteStorage<'a, Lingering>,
+        Read<'a, Tick>,
+        WriteStorage<'a, Latency>,
+        Write<'a, ChatInbox>,
     );
 
     fn run(
         &mut self,
-        (mut cm, ents, lu, mut clients, mut logging_ins/*, mut register_players*/): Self::SystemData,
+        (
+            mut cm,
+            mut ids,
+            ents,
+            lu,
+            mut net_ids,
+            mut clients,
+            mut interests,
+            mut queues,
+            config,
+            seed,
+            tiles,
+            mut logins,
+            logged_in,
+            mut lingering,
+            tick,
+            mut latencies,
+            mut inbox,
+        ): Self::SystemData,
     ) {
-        while let Ok((addr, net_msg)) = cm.from_clients.try_recv() {
-            match net_msg {
+        while let Some((addr, event)) = cm.recv() {
+            match event {
                 // The internal networking system sends this over the channel
                 // when a connection to a client has been established.
-                NetMessage::NewEnt(_) => {
+                // Nothing happens until they say Hello, though.
+                ClientEvent::Connected => {
+                    debug!("{} connected, waiting for them to say Hello", addr);
+                }
+
+                // Clients have to introduce themselves before they can do anything else.
+                ClientEvent::Message(ClientMessage::Hello {
+                    protocol_version,
+                    client_build,
+                    resume_token,
+                }) => {
                     // if we've already registered their address... they're already connected.
-                    if cm.addr_to_ent.get(&addr).is_none() {
+                    if cm.addr_to_ent.get(&addr).is_some() {
+                        warn!("{} said Hello twice", addr);
+                    } else if protocol_version != PROTOCOL_VERSION {
+                        info!(
+                            "Rejecting {} (client build {:?}), they speak protocol version {}",
+                            addr, client_build, protocol_version,
+                        );
+                        cm.reject(
+                            addr,
+                            format!(
+                                "This server speaks protocol version {}, but your client speaks \
+                                 version {}. Try refreshing the page to get the latest client.",
+                                PROTOCOL_VERSION, protocol_version,
+                            ),
+                        );
+                    } else {
+                        // if they're back in time, they get the player they left behind;
+                        let holder = resume_token.and_then(|t| cm.resume_tokens.get(&t).cloned());
+                        let resumed = match holder {
+                            Some(ent) if lingering.remove(ent).is_some() => Some(ent),
+                            // we might not have noticed their old connection's gone yet,
+                            // in which case it's hung up on, and they carry on from here.
+                            Some(ent) => match clients.get(ent).map(|Client(old)| *old) {
+                                Some(old) => {
+                                    info!(
+                                        "Client {} came back from {} before {} was noticed gone",
+                                        ent.id(),
+                                        addr,
+                                        old
+                                    );
+                                    cm.addr_to_ent.remove(&old);
+                                    cm.reject(old, "You've connected from somewhere else.".into());
+                                    Some(ent)
+                                }
+                                None => None,
+                            },
+                            None => None,
+                        };
+
                         // otherwise, welcome!
-                        let ent = ents.create();
-                        info!("New Player joined, assigned entity {}", ent.id());
+                        let (ent, id) = match resumed {
+                            Some(ent) => {
+                                let id = *net_ids.get(ent).expect("Lingering player had no NetId");
+                                info!(
+                                    "Client {} came back with client build {:?}",
+                                    ent.id(),
+                                    client_build
+                                );
+                                (ent, id)
+                            }
+                            None => {
+                                let ent = ents.create();
+                                let id = ids.alloc(ent);
+                                info!(
+                                    "New Player joined with client build {:?}, assigned entity {:?}",
+                                    client_build, id
+                                );
+                                net_ids.insert(ent, id).unwrap();
+                                (ent, id)
+                            }
+                        };
+
+                        // a token can only be used once.
+                        cm.resume_tokens.retain(|_, &mut e| e != ent);
+                        let token = ResumeToken(rand::random());
+                        cm.resume_tokens.insert(token, ent);
 
                         clients.insert(ent, Client(addr.clone())).unwrap();
-                        logging_ins.insert(ent, LoggingIn).unwrap();
-                        cm.addr_to_ent.insert(addr, ent.id());
+                        // they've forgotten everything they knew, so they're sent it all again.
+                        interests.insert(ent, Interest::default()).unwrap();
+                        latencies.insert(ent, Latency::default()).unwrap();
+                        cm.addr_to_ent.insert(addr, ent);
+
+                        cm.send(
+                            addr,
+                            ServerMessage::Welcome {
+                                your_net_id: id,
+                                tick_rate: config.tick_rate,
+                                world_seed: seed.0,
+                                server_name: SERVER_NAME.to_string(),
+                                resume_token: token,
+                                resumed: resumed.is_some(),
+                            },
+                        );
+                        // the floor is sent all at once; see `comn::art::tilemap`.
+                        cm.send(addr, ServerMessage::TileMap(tiles.clone()));
                     }
                 }
 
-                // We need to devise some way to prevent the client
-                // from inserting certain components onto themselves.
-                NetMessage::InsertComp(_, comp) => {
-                    let id = cm.addr_to_ent[&addr];
-                    trace!("inserting component to Client {}", id);
-                    let ent = ents.entity(id);
+                // Logging in is handled on its own so that passwords never end up in the logs.
+                ClientEvent::Message(ClientMessage::Login { username, password }) => {
+                    let ent = match cm.addr_to_ent.get(&addr) {
+                        Some(&ent) => ent,
+                        None => {
+                            warn!("{} tried to log in before saying Hello", addr);
+                            continue;
+                        }
+                    };
+
+                    if let Some(LoggedIn(already)) = logged_in.get(ent) {
+                        warn!(
+                            "Client {} tried to log in as {}, but they're already {}",
+                            ent.id(),
+                            username,
+                            already
+                        );
+                        continue;
+                    }
+
+                    // checking passwords takes a while on purpose, so it's done a few at a time;
+                    // see HandleLogins. Each Client only gets one try at a time.
+                    if logins.0.iter().any(|login| login.ent == ent) {
+                        warn!(
+                            "Client {} tried to log in again before hearing back",
+                            ent.id()
+                        );
+                        continue;
+                    }
+                    logins.0.push_back(PendingLogin {
+                        ent,
+                        addr,
+                        username,
+                        password,
+                    });
+                }
+
+                // Clients can only tell us what they'd like to do,
+                // so all we do here is record that on their entity for
+                // the other systems to deal with.
+                ClientEvent::Message(msg) => {
+                    let ent = match cm.addr_to_ent.get(&addr) {
+                        Some(&ent) => ent,
+                        None => {
+                            warn!("{} sent {:?} before saying Hello", addr, msg);
+                            continue;
+                        }
+                    };
+                    trace!("recording {:?} for Client {}", msg, ent.id());
                     if !ents.is_alive(ent) {
-                        panic!("Cannot insert for disconnected client!?");
-                    } else {
-                        comp.insert(ent, &lu);
+                        panic!("Cannot handle message from disconnected client!?");
+                    }
+
+                    match msg {
+                        ClientMessage::Hello { .. } | ClientMessage::Login { .. } => unreachable!(),
+                        ClientMessage::SpawnPlayer => lu.insert(ent, comn::net::SpawnPlayer),
+                        ClientMessage::Input(input) => queues
+                            .entry(ent)
+                            .expect("Couldn't get InputQueue entry for Client")
+                            .or_insert_with(InputQueue::default)
+                            .push(input),
+                        ClientMessage::PickupRequest(pickup) => lu.insert(ent, pickup),
+                        ClientMessage::DropRequest(drop) => lu.insert(ent, drop),
+                        ClientMessage::Ping(id) => cm.send(addr, ServerMessage::Pong(id)),
+                        ClientMessage::Pong(id) => {
+                            let now = cm.uptime();
+                            let rtt = latencies
+                                .get_mut(ent)
+                                .and_then(|latency| latency.pong(id, now));
+                            match rtt {
+                                Some(rtt) => trace!("Client {} round trip: {}s", ent.id(), rtt),
+                                None => debug!("Client {} sent unexpected Pong {}", ent.id(), id),
+                            }
+                        }
+                        ClientMessage::Chat(text) => inbox.0.push((ent, text)),
+                    }
+                }
+
+                // The internal networking system sends this over the channel
+                // when a client's connection is gone, however that happened.
+                ClientEvent::Disconnected => {
+                    // if they never said Hello, there's nothing to clean up.
+                    if let Some(ent) = cm.addr_to_ent.remove(&addr) {
+                        info!("Client {} disconnected", ent.id());
+                        clients.remove(ent);
+                        interests.remove(ent);
+                        latencies.remove(ent);
+
+                        // players are kept around for a while, in case they come back.
+                        if logged_in.contains(ent) && config.linger > 0.0 {
+                            let ticks = (config.linger * config.tick_rate).ceil() as u64;
+                            lingering
+                                .insert(
+                                    ent,
+                                    Lingering {
+                                        until: Tick(tick.0 + ticks),
+                                    },
+                                )
+                                .expect("Couldn't mark disconnected player as Lingering");
+                        } else {
+                            depart(&mut cm, &lu, ent);
+                        }
                     }
                 }
             }
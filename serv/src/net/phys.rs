@@ -2,34 +2,123 @@
 use super::prelude::*;
 //use log::*;
 // crates
-use comn::{specs::prelude::*, Pos};
+use comn::{controls::InputQueue, net::UpdatePosition, specs::prelude::*, Iso2, Pos};
+use std::collections::HashMap;
 
-/// This system sends the world to all clients with the LoggingIn component.
-pub struct SendNewPositions;
+/// How far something has to move before clients are told about it.
+const MIN_TRANSLATION: f32 = 0.01;
+/// How far something has to rotate (in radians) before clients are told about it.
+const MIN_ROTATION: f32 = 0.01;
+/// Every this many seconds, the position of everything that isn't Static
+/// is sent whether it's changed or not, in case a client missed something.
+const KEYFRAME_INTERVAL: f32 = 2.0;
+
+/// This system sends the positions of entities to clients,
+/// but only the ones that have moved since they were last sent,
+/// except on keyframes, when everything that isn't Static is sent.
+/// Each player is also told where they are whenever the Server
+/// applies more of their Inputs, so they can check their predictions.
+pub struct SendNewPositions {
+    /// The positions that were last sent out, by entity.
+    last_sent: HashMap<NetId, Iso2>,
+    /// The seq of the last Input each player was told had been applied.
+    last_acked: HashMap<NetId, u32>,
+    ticks: u32,
+    /// How many ticks there are between each keyframe.
+    keyframe_ticks: u32,
+}
+impl SendNewPositions {
+    pub fn new(tick_rate: f32) -> Self {
+        Self {
+            last_sent: HashMap::new(),
+            last_acked: HashMap::new(),
+            ticks: 0,
+            keyframe_ticks: ((KEYFRAME_INTERVAL * tick_rate).round() as u32).max(1),
+        }
+    }
+
+    fn moved(from: &Iso2, to: &Iso2) -> bool {
+        (from.translation.vector - to.translation.vector).magnitude_squared()
+            > MIN_TRANSLATION * MIN_TRANSLATION
+            || from.rotation.angle_to(&to.rotation).abs() > MIN_ROTATION
+    }
+}
 impl<'a> System<'a> for SendNewPositions {
     type SystemData = (
         // things we need to do networking
         Read<'a, ConnectionManager>,
-        ReadStorage<'a, LoggingIn>,
-        ReadStorage<'a, Client>,
-        // things we need to tell new players about
+        Read<'a, NetIds>,
         Entities<'a>,
+        ReadStorage<'a, Client>,
+        ReadStorage<'a, Interest>,
+        ReadStorage<'a, InputQueue>,
+        // things we need to tell players about
+        ReadStorage<'a, NetId>,
         ReadStorage<'a, Pos>,
+        ReadStorage<'a, Static>,
     );
 
-    fn run(&mut self, (cm, loggin_ins, clients, ents, isos): Self::SystemData) {
-        for (Client(addr), _) in (&clients, !&loggin_ins).join() {
-            for (Pos(iso), ent) in (&isos, &*ents).join() {
-                use std::time::{SystemTime, UNIX_EPOCH};
+    fn run(
+        &mut self,
+        (cm, ids, ents, clients, interests, queues, net_ids, isos, statics): Self::SystemData,
+    ) {
+        let keyframe = self.ticks % self.keyframe_ticks == 0;
+        self.ticks = self.ticks.wrapping_add(1);
+
+        // forget about the entities that have been deleted
+        self.last_sent.retain(|&id, _| ids.entity(id).is_some());
+        self.last_acked.retain(|&id, _| ids.entity(id).is_some());
+
+        let last_sent = &mut self.last_sent;
+        let updates = (&isos, &net_ids, !&statics)
+            .join()
+            .filter_map(|(Pos(iso), &id, _)| {
+                let moved = last_sent
+                    .get(&id)
+                    .map_or(true, |last| Self::moved(last, iso));
+                if keyframe || moved {
+                    last_sent.insert(id, iso.clone());
+                    Some((id, iso.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (player, Client(addr), interest) in (&*ents, &clients, &interests).join() {
+            let own = net_ids.get(player).cloned();
+            let ack = queues.get(player).and_then(|q| q.last_applied);
+
+            for (id, iso) in updates.iter().filter(|(id, _)| interest.follows(id)) {
+                let ack = if Some(*id) == own { ack } else { None };
                 cm.insert_comp(
                     *addr,
-                    ent,
-                    comn::net::UpdatePosition {
+                    *id,
+                    UpdatePosition {
                         iso: iso.clone(),
-                        time_stamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap(),
+                        ack,
                     },
                 );
             }
+
+            // even if those Inputs didn't move them, they still need to know
+            // that they've been applied.
+            if let (Some(own), Some(ack)) = (own, ack) {
+                let already_sent = updates.iter().any(|(id, _)| *id == own);
+                if self.last_acked.insert(own, ack) != Some(ack) && !already_sent {
+                    if let Some(Pos(iso)) = isos.get(player) {
+                        let ack = Some(ack);
+                        cm.insert_comp(
+                            *addr,
+                            own,
+                            UpdatePosition {
+                                iso: iso.clone(),
+                                ack,
+                            },
+                        );
+                    }
+                }
+            }
         }
     }
 }
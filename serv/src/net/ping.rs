@@ -0,0 +1,29 @@
+// our code
+use super::prelude::*;
+use comn::ping::Latency;
+use comn::specs::prelude::*;
+use comn::ServerMessage;
+// crates
+use log::*;
+
+/// This system pings every client each `comn::ping::PING_INTERVAL`,
+/// so that their Latency is kept up to date as their Pongs come back.
+pub struct SendPings;
+impl<'a> System<'a> for SendPings {
+    type SystemData = (
+        Read<'a, ConnectionManager>,
+        Entities<'a>,
+        ReadStorage<'a, Client>,
+        WriteStorage<'a, Latency>,
+    );
+
+    fn run(&mut self, (cm, ents, clients, mut latencies): Self::SystemData) {
+        let now = cm.uptime();
+        for (ent, Client(addr), latency) in (&*ents, &clients, &mut latencies).join() {
+            if let Some(id) = latency.ping(now) {
+                trace!("pinging Client {} with {}", ent.id(), id);
+                cm.send(*addr, ServerMessage::Ping(id));
+            }
+        }
+    }
+}
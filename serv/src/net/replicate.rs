@@ -0,0 +1,150 @@
+// our code
+use super::prelude::*;
+use comn::net::{Replicated, Replication, ReplicationVisitor};
+use comn::specs::prelude::*;
+use comn::NetComponentKind;
+// crates
+use log::*;
+use std::marker::PhantomData;
+
+/// One of these is added to the dispatcher for each sort of NetComponent that's replicated.
+/// Clients are sent every one of those components they're allowed to know about
+/// on the entities that have just entered their Interest, and are told whenever one
+/// is inserted, modified, or removed on the entities they already knew about.
+pub struct ReplicateComponent<C> {
+    reader: Option<ReaderId<ComponentEvent>>,
+    phantom: PhantomData<C>,
+}
+impl<C> Default for ReplicateComponent<C> {
+    fn default() -> Self {
+        Self {
+            reader: None,
+            phantom: PhantomData,
+        }
+    }
+}
+impl<C> ReplicateComponent<C>
+where
+    C: Replicated,
+{
+    /// Whether or not the client whose player is `client` should know
+    /// about this sort of component when it's on `ent`.
+    #[inline]
+    fn should_tell(client: Entity, ent: Entity) -> bool {
+        match C::REPLICATION {
+            Replication::Never => false,
+            Replication::Owner => client == ent,
+            Replication::Everyone => true,
+        }
+    }
+}
+impl<'a, C> System<'a> for ReplicateComponent<C>
+where
+    C: Replicated,
+    C::Storage: Tracked,
+{
+    type SystemData = (
+        Read<'a, ConnectionManager>,
+        Read<'a, NetIds>,
+        Entities<'a>,
+        ReadStorage<'a, Client>,
+        ReadStorage<'a, Interest>,
+        ReadStorage<'a, NetId>,
+        ReadStorage<'a, C>,
+    );
+
+    fn run(&mut self, (cm, ids, ents, clients, interests, net_ids, comps): Self::SystemData) {
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("ReplicateComponent wasn't setup");
+
+        for event in comps.channel().read(reader) {
+            match event {
+                // Pos is modified all the time, but SendNewPositions already streams
+                // those changes, so there's no sense in sending the whole thing again.
+                ComponentEvent::Modified(_) if C::KIND == NetComponentKind::Pos => {}
+                ComponentEvent::Inserted(index) | ComponentEvent::Modified(index) => {
+                    let ent = ents.entity(*index);
+                    if let (Some(&id), Some(comp)) = (net_ids.get(ent), comps.get(ent)) {
+                        for (client, Client(addr), interest) in
+                            (&*ents, &clients, &interests).join()
+                        {
+                            if interest.follows(&id) && Self::should_tell(client, ent) {
+                                cm.insert_comp(*addr, id, comp.clone());
+                            }
+                        }
+                    }
+                }
+                ComponentEvent::Removed(index) => {
+                    // if the entity itself is gone, RemoveEnt has already been sent.
+                    let ent = ents.entity(*index);
+                    if let Some(&id) = net_ids.get(ent) {
+                        for (client, Client(addr), interest) in
+                            (&*ents, &clients, &interests).join()
+                        {
+                            if interest.follows(&id) && Self::should_tell(client, ent) {
+                                cm.remove_comp(*addr, id, C::KIND);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (client, Client(addr), interest) in (&*ents, &clients, &interests).join() {
+            for &id in interest.entered.iter() {
+                let ent = match ids.entity(id) {
+                    Some(ent) => ent,
+                    None => continue,
+                };
+                if let Some(comp) = comps.get(ent) {
+                    if Self::should_tell(client, ent) {
+                        trace!(
+                            "telling Client {} about {:?}'s {:?}",
+                            client.id(),
+                            id,
+                            C::KIND
+                        );
+                        cm.insert_comp(*addr, id, comp.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(WriteStorage::<C>::fetch(world).register_reader());
+    }
+}
+
+/// Adds a ReplicateComponent to the dispatcher for every sort of NetComponent
+/// that's replicated, each depending on `deps`, and returns the names they were added under.
+pub fn add_replicators(builder: &mut DispatcherBuilder, deps: &[&str]) -> Vec<String> {
+    struct AddReplicators<'d, 'a, 'b> {
+        builder: &'d mut DispatcherBuilder<'a, 'b>,
+        deps: &'d [&'d str],
+        names: Vec<String>,
+    }
+    impl ReplicationVisitor for AddReplicators<'_, '_, '_> {
+        fn visit<C>(&mut self)
+        where
+            C: Replicated,
+            C::Storage: Tracked,
+        {
+            let name = format!("replicate {:?}", C::KIND);
+            self.builder
+                .add(ReplicateComponent::<C>::default(), &name, self.deps);
+            self.names.push(name);
+        }
+    }
+
+    let mut adder = AddReplicators {
+        builder,
+        deps,
+        names: Vec::new(),
+    };
+    comn::net::visit_replicated(&mut adder);
+    adder.names
+}
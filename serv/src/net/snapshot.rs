@@ -0,0 +1,16 @@
+// our code
+use super::prelude::*;
+use comn::specs::prelude::*;
+use comn::Tick;
+
+/// This system should run after everything else that sends messages to clients,
+/// because it bundles up everything they've sent this tick into Snapshots
+/// and sends those off.
+pub struct SendSnapshots;
+impl<'a> System<'a> for SendSnapshots {
+    type SystemData = (Write<'a, ConnectionManager>, Read<'a, Tick>);
+
+    fn run(&mut self, (mut cm, tick): Self::SystemData) {
+        cm.flush(*tick);
+    }
+}
@@ -0,0 +1,27 @@
+// our code
+use super::prelude::*;
+use comn::art::TileMap;
+use comn::specs::prelude::*;
+use comn::ServerMessage;
+// crates
+use log::*;
+
+/// This system tells every client about the tiles that have changed this tick.
+/// Clients get the rest of the TileMap in one go, right after they're welcomed.
+pub struct SendTileUpdates;
+impl<'a> System<'a> for SendTileUpdates {
+    type SystemData = (
+        Read<'a, ConnectionManager>,
+        Write<'a, TileMap>,
+        ReadStorage<'a, Client>,
+    );
+
+    fn run(&mut self, (cm, mut tiles, clients): Self::SystemData) {
+        for (cell, tile) in tiles.take_changes() {
+            trace!("telling everyone that {:?} is now {:?}", cell, tile);
+            for Client(addr) in clients.join() {
+                cm.send(*addr, ServerMessage::SetTile(cell, tile.clone()));
+            }
+        }
+    }
+}
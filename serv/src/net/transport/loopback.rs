@@ -0,0 +1,172 @@
+//! Clients in the same process as the Server can talk to it through one of these,
+//! without anything having to be encoded or go over a socket.
+//! This is handy for tests, and for anything else that wants to
+//! drive the Server's systems itself.
+use super::{ClientEvent, ToClient, Transport};
+use comn::{ClientMessage, Snapshot};
+// util
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::*;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+struct Shared {
+    to_game: Sender<(SocketAddr, ClientEvent)>,
+    from_clients: Receiver<(SocketAddr, ClientEvent)>,
+    to_clients: Mutex<HashMap<SocketAddr, Sender<ToClient>>>,
+    /// Each client gets a made up address; this is the port of the next one.
+    next_port: Mutex<u16>,
+}
+
+#[derive(Clone)]
+/// One of these can be handed to the ConnectionManager,
+/// and its clones used to connect LoopbackClients to it.
+pub struct Loopback(Arc<Shared>);
+impl Default for Loopback {
+    fn default() -> Self {
+        let (to_game, from_clients) = unbounded();
+        Loopback(Arc::new(Shared {
+            to_game,
+            from_clients,
+            to_clients: Mutex::new(HashMap::new()),
+            next_port: Mutex::new(1),
+        }))
+    }
+}
+impl Loopback {
+    /// Connects a new client; the Server hears about it the next time it checks.
+    pub fn connect(&self) -> LoopbackClient {
+        let addr = {
+            let mut port = self.0.next_port.lock().expect("Couldn't lock next_port");
+            *port += 1;
+            SocketAddr::from(([0, 0, 0, 0], *port - 1))
+        };
+
+        let (to_client, from_server) = unbounded();
+        self.0
+            .to_clients
+            .lock()
+            .expect("Couldn't lock loopback clients to connect")
+            .insert(addr, to_client);
+
+        let client = LoopbackClient {
+            addr,
+            to_server: self.0.to_game.clone(),
+            from_server,
+            loopback: self.clone(),
+        };
+        client.event(ClientEvent::Connected);
+        client
+    }
+}
+impl Transport for Loopback {
+    fn recv(&self) -> Option<(SocketAddr, ClientEvent)> {
+        self.0.from_clients.try_recv().ok()
+    }
+
+    fn send(&self, addr: SocketAddr, to_client: ToClient) {
+        let mut clients = self
+            .0
+            .to_clients
+            .lock()
+            .expect("Couldn't lock loopback clients to send");
+
+        let hang_up = match (clients.get(&addr), to_client) {
+            (Some(_), ToClient::Close) => true,
+            // if they've stopped listening, they've hung up on us.
+            (Some(client), to_client) => client.send(to_client).is_err(),
+            // they've left since this was sent; no worries.
+            (None, to_client) => {
+                trace!("dropping {:?} for departed client {}", to_client, addr);
+                false
+            }
+        };
+
+        if hang_up {
+            clients.remove(&addr);
+            let _ = self.0.to_game.send((addr, ClientEvent::Disconnected));
+        }
+    }
+}
+
+/// The client's end of a Loopback.
+/// The Server is told this client has disconnected when this is dropped.
+pub struct LoopbackClient {
+    pub addr: SocketAddr,
+    to_server: Sender<(SocketAddr, ClientEvent)>,
+    from_server: Receiver<ToClient>,
+    loopback: Loopback,
+}
+impl LoopbackClient {
+    fn event(&self, event: ClientEvent) {
+        self.to_server
+            .send((self.addr, event))
+            .expect("Couldn't send to loopback Server!");
+    }
+
+    pub fn send(&self, msg: ClientMessage) {
+        self.event(ClientEvent::Message(msg));
+    }
+
+    /// Every Snapshot the Server has sent since this was last called.
+    /// Once the Server hangs up on this client, nothing more will ever arrive.
+    pub fn snapshots(&self) -> Vec<Snapshot> {
+        self.from_server
+            .try_iter()
+            .filter_map(|to_client| match to_client {
+                ToClient::Snapshot(snapshot) => Some(snapshot),
+                ToClient::Close => None,
+            })
+            .collect()
+    }
+}
+impl Drop for LoopbackClient {
+    fn drop(&mut self) {
+        // if the Server already hung up on us, it won't be surprised we're gone.
+        let was_connected = self
+            .loopback
+            .0
+            .to_clients
+            .lock()
+            .map(|mut clients| clients.remove(&self.addr).is_some())
+            .unwrap_or(false);
+
+        if was_connected {
+            let _ = self.to_server.send((self.addr, ClientEvent::Disconnected));
+        }
+    }
+}
+
+#[test]
+fn loopback_round_trip() {
+    use comn::Tick;
+
+    let loopback = Loopback::default();
+    let client = loopback.connect();
+    client.send(ClientMessage::SpawnPlayer);
+
+    match loopback.recv() {
+        Some((addr, ClientEvent::Connected)) => assert_eq!(addr, client.addr),
+        other => panic!("expected Connected, got {:?}", other),
+    }
+    match loopback.recv() {
+        Some((_, ClientEvent::Message(ClientMessage::SpawnPlayer))) => {}
+        other => panic!("expected SpawnPlayer, got {:?}", other),
+    }
+
+    let snapshot = Snapshot {
+        tick: Tick(3),
+        messages: Vec::new(),
+    };
+    loopback.send(client.addr, ToClient::Snapshot(snapshot));
+    assert_eq!(client.snapshots()[0].tick, Tick(3));
+
+    drop(client);
+    match loopback.recv() {
+        Some((_, ClientEvent::Disconnected)) => {}
+        other => panic!("expected Disconnected, got {:?}", other),
+    }
+}
@@ -0,0 +1,46 @@
+//! The ConnectionManager doesn't care how it talks to clients,
+//! so long as it's through something that implements Transport.
+mod loopback;
+mod websocket;
+
+pub use loopback::{Loopback, LoopbackClient};
+pub use websocket::WebSocketTransport;
+
+use comn::{ClientMessage, Snapshot};
+use std::net::SocketAddr;
+
+#[derive(Debug)]
+/// Transports use these to tell the game loop
+/// what's going on with each of the clients.
+pub enum ClientEvent {
+    /// A connection with this client has just been established.
+    Connected,
+    /// The client would like something to happen.
+    Message(ClientMessage),
+    /// This client is gone, whether they left or we couldn't talk to them anymore.
+    /// Nothing else is heard about them after this.
+    Disconnected,
+}
+
+#[derive(Debug)]
+/// The game loop uses these to tell Transports what to send.
+pub enum ToClient {
+    Snapshot(Snapshot),
+    /// Hang up on this client.
+    Close,
+}
+
+/// Something the Server can talk to clients over.
+/// Clients are told apart by their SocketAddr, even if
+/// the Transport doesn't actually use sockets.
+pub trait Transport: Send + Sync {
+    /// The next thing that happened with one of the clients, if anything has.
+    fn recv(&self) -> Option<(SocketAddr, ClientEvent)>;
+
+    /// Sends this off to the client at this address.
+    /// It doesn't have to actually go out until `flush` is called.
+    fn send(&self, addr: SocketAddr, to_client: ToClient);
+
+    /// Called once everything that should go out this tick has been sent.
+    fn flush(&self) {}
+}
@@ -0,0 +1,325 @@
+//! Clients in the browser talk to the Server over websockets,
+//! all of which are served from one thread, which sleeps until
+//! one of their sockets (or the game loop) has something for it to do.
+use super::{ClientEvent, ToClient, Transport};
+// networking
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Poll, PollOpt, Ready, Registration, SetReadiness, Token};
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use tungstenite::{
+    handshake::{
+        server::{NoCallback, ServerHandshake},
+        MidHandshake,
+    },
+    Error as WsError, HandshakeError, Message, WebSocket,
+};
+// util
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::*;
+use std::{collections::HashMap, thread::spawn};
+// reexports/main lib
+use comn::rmps;
+
+/// See the module level documentation.
+pub struct WebSocketTransport {
+    from_clients: Receiver<(SocketAddr, ClientEvent)>,
+    to_clients: Sender<(SocketAddr, ToClient)>,
+    /// This wakes the network loop up to send what's in to_clients.
+    wake: SetReadiness,
+}
+impl WebSocketTransport {
+    /// Starts listening for websocket connections on this address.
+    pub fn bind(addr: &SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let (to_game, from_clients) = unbounded();
+        let (to_clients, from_game) = unbounded();
+        let (waker, wake) = Registration::new2();
+
+        spawn({
+            let wake = wake.clone();
+            move || run(listener, waker, wake, to_game, from_game)
+        });
+
+        Ok(Self {
+            from_clients,
+            to_clients,
+            wake,
+        })
+    }
+}
+impl Transport for WebSocketTransport {
+    fn recv(&self) -> Option<(SocketAddr, ClientEvent)> {
+        self.from_clients.try_recv().ok()
+    }
+
+    fn send(&self, addr: SocketAddr, to_client: ToClient) {
+        self.to_clients
+            .send((addr, to_client))
+            .expect("Couldn't send to network loop!");
+    }
+
+    fn flush(&self) {
+        self.wake
+            .set_readiness(Ready::readable())
+            .expect("Couldn't wake up the network loop!");
+    }
+}
+
+const LISTENER: Token = Token(0);
+/// The game loop wakes us up with this when it has something to send.
+const WAKER: Token = Token(1);
+const FIRST_CLIENT: usize = 2;
+
+type Handshake = ServerHandshake<TcpStream, NoCallback>;
+
+enum Socket {
+    Handshaking(MidHandshake<Handshake>),
+    Open(WebSocket<TcpStream>),
+}
+
+/// Whether or not something that was tried on a websocket means
+/// it can still be used; it's fine if it just couldn't finish yet.
+fn still_open<T>(addr: SocketAddr, result: tungstenite::Result<T>) -> bool {
+    match result {
+        Ok(_) => true,
+        Err(WsError::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => true,
+        Err(WsError::ConnectionClosed) | Err(WsError::AlreadyClosed) => {
+            debug!("{} closed their connection", addr);
+            false
+        }
+        Err(e) => {
+            debug!("Couldn't talk to {}: {}", addr, e);
+            false
+        }
+    }
+}
+
+/// All of these methods return false once the connection is unusable,
+/// at which point it should be dropped.
+struct Connection {
+    addr: SocketAddr,
+    /// This is only None while a handshake is being advanced,
+    /// or if the handshake failed.
+    socket: Option<Socket>,
+}
+impl Connection {
+    fn handshook(
+        &mut self,
+        result: Result<WebSocket<TcpStream>, HandshakeError<Handshake>>,
+        to_game: &Sender<(SocketAddr, ClientEvent)>,
+    ) -> bool {
+        match result {
+            Ok(websocket) => {
+                self.socket = Some(Socket::Open(websocket));
+                to_game
+                    .send((self.addr, ClientEvent::Connected))
+                    .expect("Couldn't send connection established event over channel!");
+                // they might've sent something along with the handshake.
+                self.read(to_game)
+            }
+            Err(HandshakeError::Interrupted(mid)) => {
+                self.socket = Some(Socket::Handshaking(mid));
+                true
+            }
+            Err(HandshakeError::Failure(e)) => {
+                warn!("couldn't accept handshake from {}: {}", self.addr, e);
+                false
+            }
+        }
+    }
+
+    /// Picks the handshake back up where it left off, if it's not done yet.
+    fn continue_handshake(&mut self, to_game: &Sender<(SocketAddr, ClientEvent)>) -> bool {
+        match self.socket.take() {
+            Some(Socket::Handshaking(mid)) => self.handshook(mid.handshake(), to_game),
+            other => {
+                self.socket = other;
+                true
+            }
+        }
+    }
+
+    fn readable(&mut self, to_game: &Sender<(SocketAddr, ClientEvent)>) -> bool {
+        self.continue_handshake(to_game) && self.read(to_game)
+    }
+
+    fn writable(&mut self, to_game: &Sender<(SocketAddr, ClientEvent)>) -> bool {
+        self.continue_handshake(to_game)
+            && match &mut self.socket {
+                Some(Socket::Open(websocket)) => still_open(self.addr, websocket.write_pending()),
+                _ => true,
+            }
+    }
+
+    /// Reads everything the client has sent, passing it along to the game loop.
+    fn read(&mut self, to_game: &Sender<(SocketAddr, ClientEvent)>) -> bool {
+        let websocket = match &mut self.socket {
+            Some(Socket::Open(websocket)) => websocket,
+            _ => return true,
+        };
+
+        loop {
+            match websocket.read_message() {
+                Ok(Message::Binary(data)) => match rmps::from_read_ref(&data) {
+                    Ok(msg) => to_game
+                        .send((self.addr, ClientEvent::Message(msg)))
+                        .expect("Couldn't send ClientMessage over channel!"),
+                    // they're either very out of date, or up to no good.
+                    Err(e) => {
+                        warn!("Couldn't decode ClientMessage from {}: {}", self.addr, e);
+                        let _ = websocket.close(None);
+                        let _ = websocket.write_pending();
+                        return false;
+                    }
+                },
+                Ok(Message::Close(_)) => {
+                    debug!("{} closed their connection", self.addr);
+                    // this sends back the close frame tungstenite has queued up.
+                    let _ = websocket.write_pending();
+                    return false;
+                }
+                // pings are responded to automatically, and we've no use for text.
+                Ok(_) => {}
+                result => return still_open(self.addr, result),
+            }
+        }
+    }
+
+    fn send(&mut self, to_client: ToClient) -> bool {
+        let websocket = match &mut self.socket {
+            Some(Socket::Open(websocket)) => websocket,
+            // the game loop doesn't know about them until the handshake's done.
+            _ => return true,
+        };
+
+        match to_client {
+            // if this can't all be written right away, the rest goes out
+            // once the socket is writable again.
+            ToClient::Snapshot(snapshot) => still_open(
+                self.addr,
+                websocket.write_message(Message::Binary(
+                    rmps::encode::to_vec(&snapshot).expect("Couldn't encode Snapshot!"),
+                )),
+            ),
+            ToClient::Close => {
+                // we're hanging up on them anyway,
+                // so there's no sense worrying if this fails.
+                let _ = websocket.close(None);
+                let _ = websocket.write_pending();
+                false
+            }
+        }
+    }
+}
+
+/// Serves every client that connects to `listener` until the end of time.
+/// Whatever the game loop sends over `from_game` goes out once it sets
+/// `wake` to be readable.
+fn run(
+    listener: TcpListener,
+    waker: Registration,
+    wake: SetReadiness,
+    to_game: Sender<(SocketAddr, ClientEvent)>,
+    from_game: Receiver<(SocketAddr, ToClient)>,
+) {
+    let poll = Poll::new().expect("Couldn't create Poll for network loop");
+    poll.register(&listener, LISTENER, Ready::readable(), PollOpt::edge())
+        .expect("Couldn't register listener with Poll");
+    poll.register(&waker, WAKER, Ready::readable(), PollOpt::edge())
+        .expect("Couldn't register waker with Poll");
+
+    let mut connections: HashMap<Token, Connection> = HashMap::new();
+    let mut tokens: HashMap<SocketAddr, Token> = HashMap::new();
+    let mut next_token = FIRST_CLIENT;
+    let mut events = Events::with_capacity(1024);
+
+    loop {
+        poll.poll(&mut events, None)
+            .expect("Couldn't poll for network events");
+
+        let mut closed = Vec::new();
+
+        for event in events.iter() {
+            match event.token() {
+                LISTENER => loop {
+                    let (stream, addr) = match listener.accept() {
+                        Ok(accepted) => accepted,
+                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => {
+                            warn!("couldn't accept new connection: {}", e);
+                            break;
+                        }
+                    };
+                    debug!("New client connected from {}!", addr);
+
+                    let token = Token(next_token);
+                    next_token += 1;
+                    if let Err(e) = poll.register(
+                        &stream,
+                        token,
+                        Ready::readable() | Ready::writable(),
+                        PollOpt::edge(),
+                    ) {
+                        warn!("couldn't register stream for {}: {}", addr, e);
+                        continue;
+                    }
+
+                    let mut connection = Connection { addr, socket: None };
+                    let open = connection.handshook(tungstenite::accept(stream), &to_game);
+                    connections.insert(token, connection);
+                    tokens.insert(addr, token);
+                    // if the handshake went through but what came along with it didn't,
+                    // the game loop has to hear they're gone, like anyone else.
+                    if !open {
+                        closed.push(token);
+                    }
+                },
+                // the game loop's messages are all dealt with below.
+                WAKER => wake
+                    .set_readiness(Ready::empty())
+                    .expect("Couldn't reset network loop waker"),
+                token => {
+                    if let Some(connection) = connections.get_mut(&token) {
+                        let ready = event.readiness();
+                        let open = (!ready.is_readable() || connection.readable(&to_game))
+                            && (!ready.is_writable() || connection.writable(&to_game));
+                        if !open {
+                            closed.push(token);
+                        }
+                    }
+                }
+            }
+        }
+
+        while let Ok((addr, to_client)) = from_game.try_recv() {
+            match tokens
+                .get(&addr)
+                .and_then(|t| connections.get_mut(t).map(|c| (*t, c)))
+            {
+                Some((token, connection)) => {
+                    if !connection.send(to_client) {
+                        closed.push(token);
+                    }
+                }
+                // they've left since this was sent; no worries.
+                None => trace!("dropping {:?} for departed client {}", to_client, addr),
+            }
+        }
+
+        for token in closed {
+            // dropping the Connection closes the socket,
+            // which also takes it out of the Poll.
+            if let Some(Connection { addr, socket }) = connections.remove(&token) {
+                tokens.remove(&addr);
+
+                // the game loop only heard about them if the handshake went through.
+                if let Some(Socket::Open(_)) = socket {
+                    to_game
+                        .send((addr, ClientEvent::Disconnected))
+                        .expect("Couldn't send log-off event over channel!");
+                }
+            }
+        }
+    }
+}
@@ -7,22 +7,23 @@ use log::*;
 use specs::prelude::*;
 
 /// This System processes requests from clients to pick things up.
+/// The clients find out about what happened as the Pos and Inventory components
+/// involved are replicated.
 pub struct ItemPickupDrop;
 impl<'a> System<'a> for ItemPickupDrop {
     type SystemData = (
         Entities<'a>,
-        Read<'a, ConnectionManager>,
+        Read<'a, NetIds>,
         WriteStorage<'a, DropRequest>,
         WriteStorage<'a, PickupRequest>,
         WriteStorage<'a, Pos>,
         WriteStorage<'a, Inventory>,
         ReadStorage<'a, Item>,
-        ReadStorage<'a, Client>,
     );
 
     fn run(
         &mut self,
-        (ents, cm, mut drops, mut picks, mut poses, mut invs, items, clients): Self::SystemData,
+        (ents, ids, mut drops, mut picks, mut poses, mut invs, items): Self::SystemData,
     ) {
         (&*ents, &poses, drops.drain())
             .join()
@@ -40,9 +41,9 @@ impl<'a> System<'a> for ItemPickupDrop {
                 let player_inventory = invs.get_mut(player_ent).expect(
                     "Couldn't get inventory for player to drop item",
                 );
-                let item_ent = ents.entity(match player_inventory.clear(&item_inventory_index) {
+                let item_id = match player_inventory.clear(&item_inventory_index) {
                     Ok(slot) => match slot {
-                        Some(ent) => ent,
+                        Some(id) => id,
                         None => {
                             // POSSIBLE HACKING
                             error!(
@@ -63,24 +64,26 @@ impl<'a> System<'a> for ItemPickupDrop {
                         );
                         return;
                     }
-                });
+                };
+                let item_ent = match ids.entity(item_id) {
+                    Some(ent) => ent,
+                    None => {
+                        error!(
+                            "Player[{}] had Item{:?} in their inventory, but it doesn't exist!",
+                            player_ent.id(),
+                            item_id,
+                        );
+                        return;
+                    }
+                };
 
                 // re-physicalizing the item
-                for &Client(addr) in clients.join() {
-                    cm.insert_comp(addr, item_ent, player_pos.clone());
-                }
                 poses
                     .insert(item_ent, player_pos)
                     .expect("Couldn't insert position to re-physicalize an item");
-
-                // updating the client's record of their player's inventory
-                let &Client(player_addr) = clients.get(player_ent).expect(
-                    "Couldn't get address for player to refresh their inventory after drop",
-                );
-                cm.insert_comp(player_addr, player_ent, player_inventory.clone());
             });
 
-        (&*ents, &mut invs, picks.drain(), &poses, &clients)
+        (&*ents, &mut invs, picks.drain(), &poses)
             .join()
             // who the player wants to pick up and where the player is
             .filter_map(
@@ -92,10 +95,9 @@ impl<'a> System<'a> for ItemPickupDrop {
                         translation: p_trans,
                         ..
                     }),
-                    &Client(player_addr),
                 )| {
                     info!("got request");
-                    let item_ent = ents.entity(id);
+                    let item_ent = ids.entity(id)?;
                     // get the pos of the item they want to pickup
                     // the question marks will prevent them from picking this up
                     // if the item in question doesn't have a position or item.
@@ -112,14 +114,14 @@ impl<'a> System<'a> for ItemPickupDrop {
                     // actually close enough!
                     if player_to_item_distance_squared < MAX_INTERACTION_DISTANCE_SQUARED {
                         use comn::item::Error;
-                        match player_inventory.insert(item_ent.id(), item_item) {
+                        match player_inventory.insert(id, item_item) {
                             Err(e) => match e {
                                 Error::InventoryFull => None,
                                 other_e => {
                                     // The Inventory API massively fucked up
                                     error!(
-                                        "Couldn't insert Item[{}] into Player[{}]'s inventory: {:?}",
-                                        item_ent.id(),
+                                        "Couldn't insert Item{:?} into Player[{}]'s inventory: {:?}",
+                                        id,
                                         player_ent.id(),
                                         other_e,
                                     );
@@ -128,12 +130,11 @@ impl<'a> System<'a> for ItemPickupDrop {
                             }
                             index => {
                                 info!(
-                                    "Inserting Item[{}] into Player[{}]'s inventory at index: {:?}!",
-                                    item_ent.id(),
+                                    "Inserting Item{:?} into Player[{}]'s inventory at index: {:?}!",
+                                    id,
                                     player_ent.id(),
                                     index,
                                 );
-                                cm.insert_comp(player_addr, player_ent, player_inventory.clone());
                                 Some(item_ent)
                             },
                         }
@@ -150,10 +151,6 @@ impl<'a> System<'a> for ItemPickupDrop {
                 poses
                     .remove(item_ent)
                     .expect("couldn't un-positionize an item to pick it up!");
-
-                for &Client(addr) in clients.join() {
-                    cm.insert_comp(addr, item_ent, comn::item::Deposition);
-                }
             });
     }
 }
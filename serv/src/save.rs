@@ -0,0 +1,266 @@
+//! Everything in the world that should outlive the Server is written to a save file,
+//! every so often and whenever it's shut down, then read back in when it starts up.
+//!
+//! Entities are saved as the NetComponents they'd be replicated with, so anything
+//! a Client can be told about is saved without this module having to know about it.
+//! NetIds aren't kept between runs; restored entities are given new ones,
+//! and the Inventories that referred to the old ones are fixed up to match.
+use crate::net::{Client, Lingering, NetIds, Static};
+use crate::{SpawnPoints, WorldSeed};
+use comn::art::TileMap;
+use comn::item::Inventory;
+use comn::net::{Replicated, ReplicationVisitor};
+use comn::prelude::*;
+use comn::specs::{prelude::*, world::EntitiesRes, Tracked, WorldExt};
+use comn::{Dead, NetComponent, NetId};
+// util
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{fmt, fs, io, path::Path};
+
+/// This should be bumped whenever a change is made to what's in a Save,
+/// including to any of the components saved in one, so that old saves
+/// are turned away instead of being misread.
+pub const SAVE_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize, Deserialize)]
+/// Everything that's written to a save file.
+pub struct Save {
+    /// Always SAVE_VERSION, when written by this version of the Server.
+    version: u32,
+    /// What the world was first generated from, if it was generated at all;
+    /// see `WorldSeed`.
+    seed: Option<u64>,
+    tiles: TileMap,
+    spawns: Vec<Iso2>,
+    entities: Vec<SavedEntity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// An entity, written down as the NetComponents it'd be replicated with.
+pub struct SavedEntity {
+    /// The NetId it had when it was saved, if it had one,
+    /// so that references to it can be remapped.
+    id: Option<NetId>,
+    #[serde(default)]
+    is_static: bool,
+    components: Vec<NetComponent>,
+}
+
+/// Writes down these entities, so that `restore_entities` can make them again.
+pub fn take_entities(world: &World, saving: &HashSet<Entity>) -> Vec<SavedEntity> {
+    let mut gather = Gather {
+        world,
+        saving,
+        components: BTreeMap::new(),
+    };
+    comn::net::visit_replicated(&mut gather);
+
+    let net_ids = world.read_storage::<NetId>();
+    let statics = world.read_storage::<Static>();
+    gather
+        .components
+        .into_iter()
+        .map(|(ent, components)| SavedEntity {
+            id: net_ids.get(ent).cloned(),
+            is_static: statics.contains(ent),
+            components,
+        })
+        .collect()
+}
+
+/// Makes these entities again, with new NetIds. Their components are inserted lazily,
+/// so they're only all there after the next `maintain`. Returns the new NetId
+/// of each entity that had one, by its old NetId, so references to them can be fixed up.
+pub fn restore_entities(
+    ents: &EntitiesRes,
+    lu: &LazyUpdate,
+    ids: &mut NetIds,
+    saved: Vec<SavedEntity>,
+) -> HashMap<NetId, NetId> {
+    let mut remapped = HashMap::new();
+    for saved in saved {
+        let ent = ents.create();
+
+        if let Some(old) = saved.id {
+            let new = ids.alloc(ent);
+            lu.insert(ent, new);
+            remapped.insert(old, new);
+        }
+        if saved.is_static {
+            lu.insert(ent, Static);
+        }
+        for comp in saved.components {
+            comp.insert(ent, lu);
+        }
+    }
+
+    remapped
+}
+
+#[derive(Deserialize)]
+/// Just enough of a Save to tell if the rest of it can be read.
+struct Header {
+    version: u32,
+}
+
+#[derive(Debug)]
+/// Why a save couldn't be loaded.
+pub enum SaveError {
+    Io(io::Error),
+    Parse(ron::de::Error),
+    /// The save was written by a different version of the Server.
+    Version {
+        found: u32,
+    },
+}
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "couldn't read save: {}", e),
+            SaveError::Parse(e) => write!(f, "couldn't parse save: {}", e),
+            SaveError::Version { found } => write!(
+                f,
+                "save is version {}, but this Server only reads version {}",
+                found, SAVE_VERSION
+            ),
+        }
+    }
+}
+impl std::error::Error for SaveError {}
+
+/// Writes this to a RON file, only replacing what was there before
+/// once it's been written in full.
+pub(crate) fn write_ron<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let text = ron::ser::to_string_pretty(value, Default::default())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    let partial = path.with_extension("partial");
+    fs::write(&partial, text)?;
+    fs::rename(&partial, path)
+}
+
+/// Collects every replicated component on the entities being saved.
+struct Gather<'a> {
+    world: &'a World,
+    saving: &'a HashSet<Entity>,
+    components: BTreeMap<Entity, Vec<NetComponent>>,
+}
+impl ReplicationVisitor for Gather<'_> {
+    fn visit<C>(&mut self)
+    where
+        C: Replicated,
+        C::Storage: Tracked,
+    {
+        let comps = self.world.read_storage::<C>();
+        for (ent, comp) in (&self.world.entities(), &comps).join() {
+            if self.saving.contains(&ent) {
+                self.components
+                    .entry(ent)
+                    .or_insert_with(Vec::new)
+                    .push(comp.clone().into());
+            }
+        }
+    }
+}
+
+impl Save {
+    /// Takes down everything in the world that should be saved.
+    ///
+    /// Players aren't saved, even the ones who are Lingering,
+    /// and neither is anything in their Inventories;
+    /// those are kept with their accounts instead.
+    pub fn take(world: &World) -> Self {
+        let ents = world.entities();
+        let clients = world.read_storage::<Client>();
+        let lingering = world.read_storage::<Lingering>();
+        let dead = world.read_storage::<Dead>();
+        let invs = world.read_storage::<Inventory>();
+        let ids = world.read_resource::<NetIds>();
+
+        let player = |ent| clients.contains(ent) || lingering.contains(ent);
+        let held = (&ents, &invs)
+            .join()
+            .filter(|&(ent, _)| player(ent))
+            .flat_map(|(_, inv)| inv.loose().chain(inv.reserved()))
+            .filter_map(|(_, id)| id.and_then(|id| ids.entity(id)))
+            .collect::<HashSet<_>>();
+        let saving = (&ents, !&dead)
+            .join()
+            .map(|(ent, _)| ent)
+            .filter(|&ent| !player(ent) && !held.contains(&ent))
+            .collect::<HashSet<_>>();
+
+        let entities = take_entities(world, &saving);
+
+        Self {
+            version: SAVE_VERSION,
+            seed: world.read_resource::<WorldSeed>().0,
+            tiles: TileMap::clone(&world.read_resource()),
+            spawns: world.read_resource::<SpawnPoints>().0.clone(),
+            entities,
+        }
+    }
+
+    /// Writes this save to a file, replacing whatever was there before.
+    /// The old save is only replaced once the new one has been written in full,
+    /// so a crash partway through never leaves a broken save behind.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_ron(path, self)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        let text = fs::read_to_string(path).map_err(SaveError::Io)?;
+
+        match ron::de::from_str::<Self>(&text) {
+            Ok(save) if save.version == SAVE_VERSION => Ok(save),
+            Ok(Save { version, .. }) => Err(SaveError::Version { found: version }),
+            // saves from other versions might not even parse,
+            // so the version is checked on its own before giving up.
+            Err(e) => match ron::de::from_str::<Header>(&text) {
+                Ok(Header { version }) if version != SAVE_VERSION => {
+                    Err(SaveError::Version { found: version })
+                }
+                _ => Err(SaveError::Parse(e)),
+            },
+        }
+    }
+
+    /// Puts everything in this save into the world, instead of generating a new one.
+    pub fn restore(self, world: &mut World) {
+        match self.seed {
+            Some(seed) => info!(
+                "restoring {} entities from a world generated with seed {}",
+                self.entities.len(),
+                seed
+            ),
+            None => info!(
+                "restoring {} entities from a world loaded from a map",
+                self.entities.len()
+            ),
+        }
+
+        let remapped = restore_entities(
+            &world.entities(),
+            &world.read_resource(),
+            &mut world.write_resource(),
+            self.entities,
+        );
+        world.maintain();
+
+        for inv in (&mut world.write_storage::<Inventory>()).join() {
+            inv.remap(|old| {
+                let new = remapped.get(&old).cloned();
+                if new.is_none() {
+                    warn!("save had {:?} in an Inventory, but not in the world", old);
+                }
+                new
+            });
+        }
+
+        world.insert(WorldSeed(self.seed));
+        world.insert(self.tiles);
+        world.insert(SpawnPoints(self.spawns));
+    }
+}
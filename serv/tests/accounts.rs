@@ -0,0 +1,176 @@
+use comn::item::Inventory;
+use comn::prelude::*;
+use comn::specs::{prelude::*, WorldExt};
+use comn::{ClientMessage, NetId, ServerMessage};
+use serv::accounts::LoggedIn;
+use serv::net::{Loopback, LoopbackClient, WithNetId};
+use serv::{Server, ServerConfig};
+
+mod common;
+
+/// Says Hello and logs in, handing back whatever the Server said about logging in.
+fn log_in(server: &mut Server, client: &LoopbackClient, password: &str) -> ServerMessage {
+    common::hello(client, None);
+    common::log_in(client, "ferris", password);
+    server.step(1);
+
+    common::messages(client)
+        .into_iter()
+        .find(|msg| match msg {
+            ServerMessage::LoggedIn(_) | ServerMessage::LoginFailed(_) => true,
+            _ => false,
+        })
+        .expect("Server said nothing about logging in")
+}
+
+fn player(server: &Server) -> Entity {
+    let ents = server.world.entities();
+    let logged_in = server.world.read_storage::<LoggedIn>();
+    let (ent, _) = (&ents, &logged_in)
+        .join()
+        .next()
+        .expect("nobody's logged in");
+    ent
+}
+
+#[test]
+fn characters_come_back() {
+    let loopback = Loopback::default();
+    // they're cleared away as soon as they leave, rather than Lingering.
+    let config = ServerConfig {
+        linger: 0.0,
+        ..ServerConfig::default()
+    };
+    let mut server = Server::new(config, loopback.clone());
+
+    let client = loopback.connect();
+    log_in(&mut server, &client, "crab");
+    client.send(ClientMessage::SpawnPlayer);
+    server.step(2);
+
+    // they wander off and pick something up
+    let ferris = player(&server);
+    let key = server
+        .world
+        .create_entity()
+        .with_net_id()
+        .with(Item::Misc)
+        .build();
+    let key_id = *server.world.read_storage::<NetId>().get(key).unwrap();
+    server
+        .world
+        .write_storage::<Inventory>()
+        .get_mut(ferris)
+        .unwrap()
+        .insert_loose(key_id)
+        .unwrap();
+    server
+        .world
+        .write_storage()
+        .insert(ferris, Pos::vec(Vec2::new(7.0, 3.0)))
+        .unwrap();
+
+    // then leave, taking the key with them
+    drop(client);
+    server.step(2);
+    assert!(!server.world.is_alive(key));
+
+    let client = loopback.connect();
+    match log_in(&mut server, &client, "crab") {
+        ServerMessage::LoggedIn(_) => {}
+        other => panic!("expected LoggedIn, got {:?}", other),
+    }
+    client.send(ClientMessage::SpawnPlayer);
+    server.step(2);
+
+    let ferris = player(&server);
+    let Pos(iso) = server
+        .world
+        .read_storage::<Pos>()
+        .get(ferris)
+        .unwrap()
+        .clone();
+    assert_eq!(iso.translation.vector, Vec2::new(7.0, 3.0));
+
+    let invs = server.world.read_storage::<Inventory>();
+    let held = invs
+        .get(ferris)
+        .unwrap()
+        .loose()
+        .find_map(|(_, id)| *id)
+        .expect("key didn't come back");
+    assert_ne!(held, key_id);
+    let key = server
+        .world
+        .read_resource::<serv::net::NetIds>()
+        .entity(held)
+        .unwrap();
+    assert_eq!(
+        server.world.read_storage::<Item>().get(key),
+        Some(&Item::Misc)
+    );
+}
+
+#[test]
+fn wrong_password_turned_away() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+
+    let client = loopback.connect();
+    log_in(&mut server, &client, "crab");
+    drop(client);
+    server.step(2);
+
+    let client = loopback.connect();
+    match log_in(&mut server, &client, "lobster") {
+        ServerMessage::LoginFailed(_) => {}
+        other => panic!("expected LoginFailed, got {:?}", other),
+    }
+}
+
+#[test]
+fn too_many_wrong_passwords_hung_up_on() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+
+    let client = loopback.connect();
+    log_in(&mut server, &client, "crab");
+    drop(client);
+    server.step(2);
+
+    let client = loopback.connect();
+    for _ in 1..serv::net::MAX_LOGIN_FAILURES {
+        match log_in(&mut server, &client, "lobster") {
+            ServerMessage::LoginFailed(_) => {}
+            other => panic!("expected LoginFailed, got {:?}", other),
+        }
+    }
+    common::log_in(&client, "ferris", "shrimp");
+    server.step(1);
+
+    let rejected = common::messages(&client).into_iter().any(|msg| match msg {
+        ServerMessage::Rejected(_) => true,
+        _ => false,
+    });
+    assert!(rejected);
+}
+
+#[test]
+fn only_so_many_logins_checked_each_tick() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+
+    let clients = (0..serv::net::LOGINS_PER_TICK + 1)
+        .map(|_| loopback.connect())
+        .collect::<Vec<_>>();
+    for (i, client) in clients.iter().enumerate() {
+        common::hello(client, None);
+        common::log_in(client, &format!("crab{}", i), "crab");
+    }
+
+    let logged_in = |server: &Server| server.world.read_storage::<LoggedIn>().count();
+    server.step(1);
+    assert_eq!(logged_in(&server), serv::net::LOGINS_PER_TICK);
+    server.step(1);
+    assert_eq!(logged_in(&server), clients.len());
+}
@@ -0,0 +1,141 @@
+use comn::art::TILE_SIZE;
+use comn::net::MAX_CHAT_LENGTH;
+use comn::prelude::*;
+use comn::specs::WorldExt;
+use comn::{ClientMessage, ServerMessage};
+use serv::net::{ConnectionManager, Loopback, LoopbackClient};
+use serv::{Server, ServerConfig};
+
+mod common;
+
+fn join(server: &mut Server, loopback: &Loopback, username: &str) -> LoopbackClient {
+    common::join(server, loopback, username, "hunter2").0
+}
+
+/// Everything this client's heard in chat since it last checked, as (from, text).
+fn heard(client: &LoopbackClient) -> Vec<(Option<String>, String)> {
+    common::messages(client)
+        .into_iter()
+        .filter_map(|msg| match msg {
+            ServerMessage::Chat { from, text } => Some((from, text)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn said(from: &str, text: &str) -> (Option<String>, String) {
+    (Some(from.to_string()), text.to_string())
+}
+
+#[test]
+fn everyone_hears_chat() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let alice = join(&mut server, &loopback, "alice");
+    let bob = join(&mut server, &loopback, "bob");
+
+    alice.send(ClientMessage::Chat("  hi bob ".to_string()));
+    server.step(1);
+
+    assert_eq!(heard(&alice), vec![said("alice", "hi bob")]);
+    assert_eq!(heard(&bob), vec![said("alice", "hi bob")]);
+}
+
+#[test]
+fn too_long_or_too_fast_turned_away() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let alice = join(&mut server, &loopback, "alice");
+    let bob = join(&mut server, &loopback, "bob");
+
+    alice.send(ClientMessage::Chat("a".repeat(MAX_CHAT_LENGTH + 1)));
+    server.step(1);
+    assert!(heard(&bob).is_empty());
+    match &heard(&alice)[..] {
+        [(None, _)] => {}
+        other => panic!("expected a notice, got {:?}", other),
+    }
+
+    // the burst is all used up long before these are through,
+    // and the one that was too long counted against it too.
+    for i in 0..20 {
+        alice.send(ClientMessage::Chat(i.to_string()));
+    }
+    server.step(1);
+    assert_eq!(heard(&bob).len(), serv::chat::CHAT_BURST as usize - 1);
+    assert!(heard(&alice)
+        .iter()
+        .any(|(from, text)| from.is_none() && text == "Slow down!"));
+}
+
+#[test]
+fn only_those_nearby_hear_with_chat_radius() {
+    let loopback = Loopback::default();
+    let config = ServerConfig {
+        chat_radius: Some(5.0),
+        ..ServerConfig::default()
+    };
+    let mut server = Server::new(config, loopback.clone());
+    let alice = join(&mut server, &loopback, "alice");
+    let bob = join(&mut server, &loopback, "bob");
+
+    let ent = |client: &LoopbackClient| {
+        server
+            .world
+            .read_resource::<ConnectionManager>()
+            .addr_to_ent[&client.addr]
+    };
+    let (alice_ent, bob_ent) = (ent(&alice), ent(&bob));
+    let Pos(iso) = server
+        .world
+        .read_storage::<Pos>()
+        .get(alice_ent)
+        .unwrap()
+        .clone();
+    let move_bob = |server: &mut Server, tiles_away: f32| {
+        let at = iso.translation.vector + Vec2::x() * tiles_away * TILE_SIZE;
+        server
+            .world
+            .write_storage()
+            .insert(bob_ent, Pos::vec(at))
+            .unwrap();
+    };
+
+    // chat_radius is in tiles, not world units.
+    move_bob(&mut server, 4.0);
+    alice.send(ClientMessage::Chat("hey bob".to_string()));
+    server.step(1);
+    assert_eq!(heard(&bob), vec![said("alice", "hey bob")]);
+
+    // bob wanders off
+    move_bob(&mut server, 50.0);
+    alice.send(ClientMessage::Chat("anyone there?".to_string()));
+    server.step(1);
+
+    assert_eq!(
+        heard(&alice),
+        vec![said("alice", "hey bob"), said("alice", "anyone there?")]
+    );
+    assert!(heard(&bob).is_empty());
+}
+
+#[test]
+fn who_lists_players() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let alice = join(&mut server, &loopback, "alice");
+    let bob = join(&mut server, &loopback, "bob");
+
+    bob.send(ClientMessage::Chat("/WHO".to_string()));
+    alice.send(ClientMessage::Chat("/whomst".to_string()));
+    server.step(1);
+
+    assert_eq!(
+        heard(&bob),
+        vec![(None, "2 playing: alice, bob".to_string())]
+    );
+    match &heard(&alice)[..] {
+        [(None, text)] => assert!(text.contains("/whomst")),
+        other => panic!("expected a notice, got {:?}", other),
+    }
+}
@@ -0,0 +1,51 @@
+//! What the tests that talk to a Server like a Client would all need to do,
+//! so that they only have to change here when the protocol does.
+//! Not every test uses all of these.
+#![allow(dead_code)]
+use comn::net::ResumeToken;
+use comn::{ClientMessage, ServerMessage, PROTOCOL_VERSION};
+use serv::net::{Loopback, LoopbackClient};
+use serv::Server;
+
+/// Says Hello like an up to date Client would.
+pub fn hello(client: &LoopbackClient, resume_token: Option<ResumeToken>) {
+    client.send(ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        client_build: "test".to_string(),
+        resume_token,
+    });
+}
+
+pub fn log_in(client: &LoopbackClient, username: &str, password: &str) {
+    client.send(ClientMessage::Login {
+        username: username.to_string(),
+        password: password.to_string(),
+    });
+}
+
+/// Whatever this client's been told since it last checked.
+pub fn messages(client: &LoopbackClient) -> Vec<ServerMessage> {
+    client
+        .snapshots()
+        .into_iter()
+        .flat_map(|snapshot| snapshot.messages)
+        .collect()
+}
+
+/// Connects a player, logs them in and spawns them,
+/// handing them back with everything they were told along the way.
+pub fn join(
+    server: &mut Server,
+    loopback: &Loopback,
+    username: &str,
+    password: &str,
+) -> (LoopbackClient, Vec<ServerMessage>) {
+    let client = loopback.connect();
+    hello(&client, None);
+    log_in(&client, username, password);
+    client.send(ClientMessage::SpawnPlayer);
+    // one tick to ask, one to be spawned in, and one to hear about it
+    server.step(3);
+    let messages = messages(&client);
+    (client, messages)
+}
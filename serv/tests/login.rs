@@ -0,0 +1,73 @@
+use comn::specs::WorldExt;
+use comn::{ClientMessage, NetComponent, ServerMessage, PROTOCOL_VERSION};
+use serv::net::Loopback;
+use serv::{Server, ServerConfig};
+
+mod common;
+
+#[test]
+fn welcomed_then_spawned() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let client = loopback.connect();
+
+    common::hello(&client, None);
+    server.step(1);
+    let your_id = match &client.snapshots()[0].messages[0] {
+        ServerMessage::Welcome { your_net_id, .. } => *your_net_id,
+        other => panic!("expected Welcome, got {:?}", other),
+    };
+
+    common::log_in(&client, "ferris", "crab");
+    server.step(1);
+    assert!(common::messages(&client).iter().any(|msg| match msg {
+        ServerMessage::LoggedIn(username) => username == "ferris",
+        _ => false,
+    }));
+
+    client.send(ClientMessage::SpawnPlayer);
+    // one tick to ask, one to be spawned in, and one to hear about it
+    server.step(3);
+    assert!(common::messages(&client).iter().any(|msg| match msg {
+        ServerMessage::InsertComp(id, NetComponent::LocalPlayer(_)) => *id == your_id,
+        _ => false,
+    }));
+}
+
+#[test]
+fn rejected_for_wrong_protocol() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let client = loopback.connect();
+
+    client.send(ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION + 1,
+        client_build: "test".to_string(),
+        resume_token: None,
+    });
+    server.step(1);
+    match &client.snapshots()[0].messages[0] {
+        ServerMessage::Rejected(_) => {}
+        other => panic!("expected Rejected, got {:?}", other),
+    }
+    assert!(server
+        .world
+        .read_resource::<serv::net::ConnectionManager>()
+        .addr_to_ent
+        .is_empty());
+}
+
+#[test]
+fn not_spawned_without_logging_in() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let client = loopback.connect();
+
+    common::hello(&client, None);
+    client.send(ClientMessage::SpawnPlayer);
+    server.step(3);
+    assert!(!common::messages(&client).iter().any(|msg| match msg {
+        ServerMessage::InsertComp(_, NetComponent::LocalPlayer(_)) => true,
+        _ => false,
+    }));
+}
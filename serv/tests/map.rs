@@ -0,0 +1,64 @@
+use comn::art::{Appearance, TileMap};
+use comn::prelude::*;
+use comn::specs::{prelude::*, WorldExt};
+use serv::map::{Map, MapError};
+use serv::net::Loopback;
+use serv::{Server, ServerConfig, SpawnPoints, WorldSeed};
+
+#[test]
+fn example_map_loads() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("maps/example.ron");
+    let map = Map::load(&path).unwrap_or_else(|e| panic!("{}", e));
+
+    let mut server = Server::new(ServerConfig::default(), Loopback::default());
+    map.build(&mut server.world);
+
+    let keys = (
+        &server.world.read_storage::<Appearance>(),
+        &server.world.read_storage::<Item>(),
+    )
+        .join()
+        .filter(|(appearance, _)| **appearance == Appearance::Key)
+        .count();
+    assert_eq!(keys, 1);
+    let tiles = server.world.read_resource::<TileMap>();
+    assert_eq!((tiles.width(), tiles.height()), (11, 8));
+    assert_eq!(tiles.get((0, 0)), Some(&Appearance::RockHole));
+    assert_eq!(tiles.get((3, 1)), Some(&Appearance::SpottedRock));
+    assert_eq!(server.world.read_resource::<SpawnPoints>().0.len(), 2);
+    // there's no seed that'd make this world again.
+    assert_eq!(server.world.read_resource::<WorldSeed>().0, None);
+}
+
+#[test]
+fn unknown_appearance_says_where() {
+    let text = "Map(\n    legend: { '.': Rock },\n    layers: [[\".\"]],\n    spawns: [(0.0, 0.0)],\n    entities: [\n        (appearance: Boulder, at: (1.0, 1.0)),\n    ],\n)";
+
+    match Map::parse(text) {
+        Err(MapError::Syntax { line, message, .. }) => {
+            assert_eq!(line, 6);
+            assert!(message.contains("Boulder"), "{}", message);
+            assert!(message.starts_with("entities[0].appearance"), "{}", message);
+        }
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_tile_says_where() {
+    let text = "Map(legend: { '.': Rock }, layers: [[\"..\", \".x\"]], spawns: [(0.0, 0.0)])";
+
+    match Map::parse(text) {
+        Err(MapError::UnknownTile {
+            layer,
+            row,
+            column,
+            tile,
+            at,
+        }) => {
+            assert_eq!((layer, row, column, tile), (1, 2, 2, 'x'));
+            assert_eq!(at, Some((1, 46)));
+        }
+        other => panic!("expected an unknown tile, got {:?}", other),
+    }
+}
@@ -0,0 +1,61 @@
+use comn::item::{Inventory, PickupRequest};
+use comn::prelude::*;
+use comn::specs::prelude::*;
+use comn::NetId;
+use serv::net::{Loopback, WithNetId};
+use serv::{Server, ServerConfig};
+
+fn server() -> Server {
+    Server::new(ServerConfig::default(), Loopback::default())
+}
+
+fn key(server: &mut Server, x: f32) -> (Entity, NetId) {
+    let ent = server
+        .world
+        .create_entity()
+        .with_net_id()
+        .with(Item::Misc)
+        .with(Pos::vec(Vec2::new(x, 0.0)))
+        .build();
+    let id = *server.world.read_storage::<NetId>().get(ent).unwrap();
+    (ent, id)
+}
+
+fn holds(server: &Server, player: Entity, id: NetId) -> bool {
+    let invs = server.world.read_storage::<Inventory>();
+    let inv = invs.get(player).unwrap();
+    let held = inv
+        .loose()
+        .chain(inv.reserved())
+        .any(|(_, slot)| *slot == Some(id));
+    held
+}
+
+#[test]
+fn pickup_only_within_reach() {
+    let mut server = server();
+    let player = server
+        .world
+        .create_entity()
+        .with_net_id()
+        .with(Pos::vec(Vec2::zeros()))
+        .with(Inventory::character())
+        .build();
+    let (near, near_id) = key(&mut server, 1.0);
+    let (far, far_id) = key(&mut server, 5.0);
+
+    for id in &[near_id, far_id] {
+        server
+            .world
+            .write_storage()
+            .insert(player, PickupRequest { id: *id })
+            .unwrap();
+        server.step(1);
+    }
+
+    assert!(holds(&server, player, near_id));
+    assert!(server.world.read_storage::<Pos>().get(near).is_none());
+
+    assert!(!holds(&server, player, far_id));
+    assert!(server.world.read_storage::<Pos>().get(far).is_some());
+}
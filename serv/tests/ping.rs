@@ -0,0 +1,42 @@
+use comn::ping::Latency;
+use comn::specs::WorldExt;
+use comn::{ClientMessage, ServerMessage};
+use serv::net::{ConnectionManager, Loopback};
+use serv::{Server, ServerConfig};
+
+mod common;
+
+#[test]
+fn pings_answered_both_ways() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let client = loopback.connect();
+
+    common::hello(&client, None);
+    client.send(ClientMessage::Ping(7));
+    server.step(1);
+
+    let messages = common::messages(&client);
+    assert!(messages.iter().any(|msg| match msg {
+        ServerMessage::Pong(7) => true,
+        _ => false,
+    }));
+
+    // the Server pings new clients right away.
+    let ping = messages
+        .iter()
+        .find_map(|msg| match msg {
+            ServerMessage::Ping(id) => Some(*id),
+            _ => None,
+        })
+        .expect("wasn't pinged");
+    client.send(ClientMessage::Pong(ping));
+    server.step(1);
+
+    let ent = server
+        .world
+        .read_resource::<ConnectionManager>()
+        .addr_to_ent[&client.addr];
+    let latencies = server.world.read_storage::<Latency>();
+    assert!(latencies.get(ent).unwrap().rtt().is_some());
+}
@@ -0,0 +1,172 @@
+use comn::net::ResumeToken;
+use comn::prelude::*;
+use comn::specs::WorldExt;
+use comn::{NetComponent, NetId, ServerMessage};
+use serv::net::{Lingering, Loopback, LoopbackClient};
+use serv::{Server, ServerConfig};
+
+mod common;
+use common::{hello, messages};
+
+/// The Welcome in these messages, as (your_net_id, resume_token, resumed).
+fn welcome(messages: &[ServerMessage]) -> (NetId, ResumeToken, bool) {
+    messages
+        .iter()
+        .find_map(|msg| match msg {
+            ServerMessage::Welcome {
+                your_net_id,
+                resume_token,
+                resumed,
+                ..
+            } => Some((*your_net_id, *resume_token, *resumed)),
+            _ => None,
+        })
+        .expect("wasn't welcomed")
+}
+
+/// Connects ferris, logs them in and spawns them, handing back their
+/// NetId and the token they were given.
+fn join(server: &mut Server, loopback: &Loopback) -> (LoopbackClient, NetId, ResumeToken) {
+    let (client, messages) = common::join(server, loopback, "ferris", "crab");
+    let (id, token, resumed) = welcome(&messages);
+    assert!(!resumed);
+    (client, id, token)
+}
+
+fn lingering(server: &Server) -> usize {
+    use comn::specs::Join;
+    server.world.read_storage::<Lingering>().join().count()
+}
+
+#[test]
+fn back_in_time_gets_same_player() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let (client, id, token) = join(&mut server, &loopback);
+
+    drop(client);
+    server.step(2);
+    assert_eq!(lingering(&server), 1);
+
+    let client = loopback.connect();
+    hello(&client, Some(token));
+    server.step(2);
+    let messages = messages(&client);
+    let (resumed_id, new_token, resumed) = welcome(&messages);
+    assert!(resumed);
+    assert_eq!(resumed_id, id);
+    assert_ne!(new_token, token);
+    assert_eq!(lingering(&server), 0);
+
+    // everything's sent again, since the Client has forgotten it all.
+    assert!(messages.iter().any(|msg| match msg {
+        ServerMessage::InsertComp(i, NetComponent::LocalPlayer(_)) => *i == id,
+        _ => false,
+    }));
+
+    // tokens only work once.
+    let thief = loopback.connect();
+    hello(&thief, Some(token));
+    server.step(1);
+    let (thief_id, _, resumed) = welcome(&self::messages(&thief));
+    assert!(!resumed);
+    assert_ne!(thief_id, id);
+}
+
+#[test]
+fn back_before_old_connection_noticed_gone() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let (old, id, token) = join(&mut server, &loopback);
+
+    // their old connection is still hanging on, as far as the Server can tell.
+    let client = loopback.connect();
+    hello(&client, Some(token));
+    server.step(2);
+    let (resumed_id, _, resumed) = welcome(&messages(&client));
+    assert!(resumed);
+    assert_eq!(resumed_id, id);
+    assert!(messages(&old).iter().any(|msg| match msg {
+        ServerMessage::Rejected(_) => true,
+        _ => false,
+    }));
+
+    // and when it finally goes, their player stays right where it is.
+    drop(old);
+    server.step(2);
+    assert_eq!(lingering(&server), 0);
+    let ent = server
+        .world
+        .read_resource::<serv::net::NetIds>()
+        .entity(id)
+        .expect("player was cleared away");
+    assert!(server.world.is_alive(ent));
+    let addr_to_ent = &server
+        .world
+        .read_resource::<serv::net::ConnectionManager>()
+        .addr_to_ent;
+    assert_eq!(addr_to_ent.get(&client.addr), Some(&ent));
+}
+
+#[test]
+fn too_late_starts_over() {
+    let loopback = Loopback::default();
+    let config = ServerConfig {
+        linger: 0.1,
+        ..ServerConfig::default()
+    };
+    let mut server = Server::new(config, loopback.clone());
+    let (client, id, token) = join(&mut server, &loopback);
+
+    drop(client);
+    server.step(5);
+    assert_eq!(lingering(&server), 0);
+    assert!(server
+        .world
+        .read_resource::<serv::net::NetIds>()
+        .entity(id)
+        .is_none());
+
+    let client = loopback.connect();
+    hello(&client, Some(token));
+    server.step(1);
+    let (_, _, resumed) = welcome(&messages(&client));
+    assert!(!resumed);
+}
+
+#[test]
+fn logging_in_again_takes_over() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    let (client, id, _) = join(&mut server, &loopback);
+
+    let ferris = server
+        .world
+        .read_resource::<serv::net::NetIds>()
+        .entity(id)
+        .unwrap();
+    server
+        .world
+        .write_storage()
+        .insert(ferris, Pos::vec(Vec2::new(5.0, 2.0)))
+        .unwrap();
+
+    // they've lost their token, say by refreshing the page.
+    drop(client);
+    server.step(2);
+    let (_client, new_id, _) = join(&mut server, &loopback);
+    assert_eq!(lingering(&server), 0);
+
+    let ferris = server
+        .world
+        .read_resource::<serv::net::NetIds>()
+        .entity(new_id)
+        .unwrap();
+    let Pos(iso) = server
+        .world
+        .read_storage::<Pos>()
+        .get(ferris)
+        .unwrap()
+        .clone();
+    assert_eq!(iso.translation.vector, Vec2::new(5.0, 2.0));
+}
@@ -0,0 +1,106 @@
+use comn::art::{Appearance, TileMap};
+use comn::item::Inventory;
+use comn::prelude::*;
+use comn::specs::{prelude::*, WorldExt};
+use comn::NetId;
+use serv::net::{Loopback, NetIds, WithNetId};
+use serv::save::{Save, SaveError};
+use serv::{Server, ServerConfig, WorldSeed};
+use std::path::PathBuf;
+
+fn server() -> Server {
+    Server::new(ServerConfig::default(), Loopback::default())
+}
+
+fn save_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("storked-{}-{}.ron", name, std::process::id()))
+}
+
+#[test]
+fn inventories_survive_restart() {
+    let path = save_path("restart");
+    let mut before = server();
+    // seeds this big don't fit in an i64, which is easy to trip over
+    let seed = u64::max_value() - 3;
+    serv::generate_world(&mut before.world, seed);
+
+    // a chest with a key in it, and a key on the ground
+    let mut chest_inv = Inventory::new_loose(1, 1);
+    let held = before
+        .world
+        .create_entity()
+        .with_net_id()
+        .with(Item::Misc)
+        .with(Appearance::Key)
+        .build();
+    let held_id = *before.world.read_storage::<NetId>().get(held).unwrap();
+    chest_inv.insert_loose(held_id).unwrap();
+    before
+        .world
+        .create_entity()
+        .with_net_id()
+        .with(Pos::vec(Vec2::new(4.0, 4.0)))
+        .with(chest_inv)
+        .build();
+    before
+        .world
+        .create_entity()
+        .with_net_id()
+        .with(Item::Misc)
+        .with(Appearance::Key)
+        .with(Pos::vec(Vec2::new(6.0, 4.0)))
+        .build();
+
+    Save::take(&before.world).write(&path).unwrap();
+    let save = Save::read(&path).unwrap_or_else(|e| panic!("{}", e));
+    std::fs::remove_file(&path).unwrap();
+
+    // a few NetIds are used up first, so the old ones can't line up by accident.
+    let mut after = server();
+    for _ in 0..3 {
+        after.world.create_entity().with_net_id().build();
+    }
+    save.restore(&mut after.world);
+
+    let keys = |server: &Server| {
+        let items = server.world.read_storage::<Item>();
+        let poses = server.world.read_storage::<Pos>();
+        (&items, poses.maybe())
+            .join()
+            .map(|(_, pos)| pos.map(|Pos(iso)| iso.translation.vector))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(keys(&before), keys(&after));
+    assert_eq!(
+        after.world.read_resource::<TileMap>().tiles().count(),
+        before.world.read_resource::<TileMap>().tiles().count(),
+    );
+    assert_eq!(after.world.read_resource::<WorldSeed>().0, Some(seed));
+
+    // the chest should hold the restored key, under its new NetId
+    let ids = after.world.read_resource::<NetIds>();
+    let items = after.world.read_storage::<Item>();
+    let invs = after.world.read_storage::<Inventory>();
+    let holding = (&invs)
+        .join()
+        .flat_map(|inv| inv.loose().map(|(_, slot)| *slot).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(holding.len(), 1);
+    let restored = holding[0]
+        .and_then(|id| ids.entity(id))
+        .expect("key not remapped");
+    assert!(items.get(restored).is_some());
+}
+
+#[test]
+fn other_versions_turned_away() {
+    let path = save_path("version");
+    std::fs::write(&path, "(version: 0, seed: 1, tiles: ())").unwrap();
+    let result = Save::read(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    match result {
+        Err(SaveError::Version { found: 0 }) => {}
+        other => panic!("expected a version error, got {:?}", other),
+    }
+}
@@ -0,0 +1,43 @@
+use comn::art::{Appearance, TileMap};
+use comn::specs::WorldExt;
+use comn::ServerMessage;
+use serv::net::Loopback;
+use serv::{Server, ServerConfig};
+
+mod common;
+
+#[test]
+fn tilemap_sent_once_then_changes() {
+    let loopback = Loopback::default();
+    let mut server = Server::new(ServerConfig::default(), loopback.clone());
+    serv::generate_world(&mut server.world, 7);
+    let client = loopback.connect();
+
+    common::hello(&client, None);
+    server.step(1);
+    let messages = common::messages(&client);
+    let sent = match &messages[1] {
+        ServerMessage::TileMap(sent) => sent,
+        other => panic!("expected TileMap after Welcome, got {:?}", other),
+    };
+    let size = server.world.read_resource::<ServerConfig>().world_size as usize;
+    assert_eq!((sent.width(), sent.height()), (size, size));
+    assert!(!messages.iter().any(|msg| match msg {
+        ServerMessage::SetTile(..) => true,
+        _ => false,
+    }));
+
+    server
+        .world
+        .write_resource::<TileMap>()
+        .set((1, 1), Some(Appearance::Key));
+    server.step(1);
+    let set = common::messages(&client)
+        .into_iter()
+        .filter_map(|msg| match msg {
+            ServerMessage::SetTile(cell, tile) => Some((cell, tile)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(set, vec![((1, 1), Some(Appearance::Key))]);
+}
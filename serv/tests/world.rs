@@ -0,0 +1,59 @@
+use comn::art::{Appearance, TileMap};
+use comn::prelude::*;
+use comn::specs::{prelude::*, WorldExt};
+use serv::net::Loopback;
+use serv::{Server, ServerConfig};
+
+/// What's where, in a way that can be compared between worlds.
+fn layout(seed: u64) -> Vec<String> {
+    let mut server = Server::new(ServerConfig::default(), Loopback::default());
+    serv::generate_world(&mut server.world, seed);
+
+    let appearances = server.world.read_storage::<Appearance>();
+    let poses = server.world.read_storage::<Pos>();
+    let tiles = server.world.read_resource::<TileMap>();
+    let layout = (&appearances, &poses)
+        .join()
+        .map(|(appearance, Pos(iso))| format!("{:?} {}", appearance, iso.translation.vector))
+        .chain(
+            tiles
+                .tiles()
+                .map(|(cell, appearance)| format!("{:?} tile {:?}", appearance, cell)),
+        )
+        .collect();
+    layout
+}
+
+#[test]
+fn same_seed_same_world() {
+    assert_eq!(layout(42), layout(42));
+    assert_ne!(layout(42), layout(43));
+}
+
+#[test]
+fn every_floor_is_reachable_from_spawn() {
+    use rand::{rngs::StdRng, SeedableRng};
+    use serv::cave::{Cave, CaveConfig};
+    use std::collections::HashSet;
+
+    for seed in 0..20 {
+        let size = 32;
+        let cave = Cave::generate(
+            size,
+            Cave::center(size),
+            &CaveConfig::default(),
+            &mut StdRng::seed_from_u64(seed),
+        );
+        assert!(cave.is_floor(cave.spawn));
+
+        let mut reached = HashSet::new();
+        let mut frontier = vec![cave.spawn];
+        while let Some((x, y)) = frontier.pop() {
+            if cave.is_floor((x, y)) && reached.insert((x, y)) {
+                frontier.extend(&[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+            }
+        }
+
+        assert_eq!(reached.len(), cave.floors().count(), "seed {}", seed);
+    }
+}